@@ -0,0 +1,100 @@
+use itertools::Itertools;
+
+/// A grid of up to 128 columns where each row is packed into a `u128`, bit `x` being column `x`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitGrid {
+    rows: Vec<u128>,
+    width: usize,
+}
+
+/// Returns `len` set bits starting at bit `start`.
+pub fn span(start: usize, len: usize) -> u128 {
+    if len == 0 {
+        return 0;
+    }
+    (u128::MAX >> (128 - len)) << start
+}
+
+impl BitGrid {
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width <= 128, "BitGrid rows are limited to 128 columns");
+        Self {
+            rows: vec![0; height],
+            width,
+        }
+    }
+
+    /// Builds a grid from newline separated text, setting every cell equal to `set`. Rows must
+    /// all be the same length, and both sides at most 128 long so the grid can be transposed.
+    pub fn parse(s: &str, set: u8) -> anyhow::Result<Self> {
+        let lines = s.lines().collect_vec();
+        let width = lines.first().map(|l| l.len()).unwrap_or(0);
+        if let Some(y) = lines.iter().position(|l| l.len() != width) {
+            return Err(anyhow!(
+                "Row {} has {} cells but the first row has {}",
+                y,
+                lines[y].len(),
+                width
+            ));
+        }
+        if width > 128 || lines.len() > 128 {
+            return Err(anyhow!(
+                "A {}x{} grid does not fit in 128 bit rows",
+                width,
+                lines.len()
+            ));
+        }
+
+        let mut grid = Self::new(width, lines.len());
+        for (y, line) in lines.iter().enumerate() {
+            for (x, byte) in line.bytes().enumerate() {
+                if byte == set {
+                    grid.set(x, y, true);
+                }
+            }
+        }
+        Ok(grid)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn rows(&self) -> &[u128] {
+        &self.rows
+    }
+
+    pub fn rows_mut(&mut self) -> &mut [u128] {
+        &mut self.rows
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y] >> x & 1 == 1
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: bool) {
+        if value {
+            self.rows[y] |= 1 << x;
+        } else {
+            self.rows[y] &= !(1 << x);
+        }
+    }
+
+    /// Swaps rows and columns, so that column `x` becomes row `x`.
+    pub fn transpose(&self) -> Self {
+        let mut transposed = Self::new(self.height(), self.width);
+        for (y, row) in self.rows.iter().enumerate() {
+            let mut bits = *row;
+            while bits != 0 {
+                let x = bits.trailing_zeros() as usize;
+                transposed.rows[x] |= 1 << y;
+                bits &= bits - 1;
+            }
+        }
+        transposed
+    }
+}
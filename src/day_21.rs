@@ -1,6 +1,6 @@
-use std::{collections::HashSet, io::Write};
+use std::collections::{HashSet, VecDeque};
 
-use crate::Args;
+use crate::{render::render_to_file, series::Series, Args};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_21.txt");
 
@@ -29,67 +29,196 @@ fn starting_point(input: &[Vec<u8>]) -> (usize, usize) {
         .unwrap()
 }
 
-fn adjacent_positions(input: &[Vec<u8>], (x, y): (usize, usize)) -> Vec<(usize, usize)> {
-    let mut positions = Vec::new();
-    if x > 0 {
-        positions.push((x - 1, y));
-    }
-    if y > 0 {
-        positions.push((x, y - 1));
-    }
-    if x < input[y].len() - 1 {
-        positions.push((x + 1, y));
-    }
-    if y < input.len() - 1 {
-        positions.push((x, y + 1));
+fn adjacent_positions_infinite((x, y): (isize, isize)) -> [(isize, isize); 4] {
+    [(x - 1, y), (x, y - 1), (x + 1, y), (x, y + 1)]
+}
+
+fn adjacent_positions_bounded(
+    (width, height): (usize, usize),
+    (x, y): (usize, usize),
+) -> impl Iterator<Item = (usize, usize)> {
+    [
+        x.checked_sub(1).map(|x| (x, y)),
+        y.checked_sub(1).map(|y| (x, y)),
+        (x + 1 < width).then_some((x + 1, y)),
+        (y + 1 < height).then_some((x, y + 1)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Brute force simulation of the infinite garden, kept to cross-check `infinite_reachable`.
+fn brute_force(input: &[Vec<u8>], steps: u64) -> usize {
+    let y_len = input.len() as isize;
+    let x_len = input[0].len() as isize;
+    let (x, y) = starting_point(input);
+    let mut positions = HashSet::from([(x as isize, y as isize)]);
+
+    for _ in 0..steps {
+        positions = positions
+            .iter()
+            .flat_map(|position| adjacent_positions_infinite(*position))
+            .filter(|(x, y)| {
+                input[y.rem_euclid(y_len) as usize][x.rem_euclid(x_len) as usize] != b'#'
+            })
+            .collect();
     }
-    positions
+
+    positions.len()
 }
 
-fn adjacent_positions_infinite((x, y): (isize, isize)) -> Vec<(isize, isize)> {
-    vec![(x - 1, y), (x, y - 1), (x + 1, y), (x, y + 1)]
+/// Walking distances from the start to every plot of the garden repeated `tiles` times in
+/// each direction, with the start in the middle copy.
+struct DistanceMap {
+    distances: Vec<Option<u32>>,
+    /// Entry `d` is the number of plots at distance `d`, `d - 2`, `d - 4` and so on, i.e.
+    /// those that can be stood on after exactly `d` steps.
+    reachable: Vec<usize>,
+    width: usize,
+    /// Distance from the start to the nearest cell outside the map. Step counts below this
+    /// are answered exactly.
+    radius: u32,
 }
 
-fn step(input: &[Vec<u8>], positions: &mut Vec<(usize, usize)>) {
-    let mut new_positions = HashSet::with_capacity(positions.len() * 4);
+impl DistanceMap {
+    pub fn new(input: &[Vec<u8>], tiles: usize) -> Self {
+        assert!(tiles % 2 == 1, "The start must be in the middle tile");
+        let (height, width) = (input.len(), input[0].len());
+        let (map_width, map_height) = (width * tiles, height * tiles);
+        let (x, y) = starting_point(input);
+        let start = (x + tiles / 2 * width, y + tiles / 2 * height);
 
-    for (x, y) in positions.drain(..) {
-        for (x, y) in adjacent_positions(input, (x, y)) {
-            if input[y][x] == b'.' || input[y][x] == b'S' {
-                new_positions.insert((x, y));
+        let mut distances = vec![None; map_width * map_height];
+        distances[start.1 * map_width + start.0] = Some(0);
+        let mut queue = VecDeque::from([start]);
+        while let Some((x, y)) = queue.pop_front() {
+            let distance = distances[y * map_width + x].unwrap();
+            for (x, y) in adjacent_positions_bounded((map_width, map_height), (x, y)) {
+                let cell = &mut distances[y * map_width + x];
+                if cell.is_none() && input[y % height][x % width] != b'#' {
+                    *cell = Some(distance + 1);
+                    queue.push_back((x, y));
+                }
             }
         }
+
+        let furthest = distances.iter().flatten().max().copied().unwrap_or(0) as usize;
+        let mut reachable = vec![0; furthest + 1];
+        for distance in distances.iter().flatten() {
+            reachable[*distance as usize] += 1;
+        }
+        for d in 2..reachable.len() {
+            reachable[d] += reachable[d - 2];
+        }
+
+        let radius = [start.0, start.1, map_width - start.0, map_height - start.1]
+            .into_iter()
+            .min()
+            .unwrap() as u32;
+        Self {
+            distances,
+            reachable,
+            width: map_width,
+            radius,
+        }
+    }
+
+    /// Whether a plot this far away can be stood on after exactly `steps` steps. Anything at
+    /// or within that distance with the same parity can, as the elf can step back and forth.
+    fn within(distance: u32, steps: u64) -> bool {
+        let distance = distance as u64;
+        distance <= steps && distance % 2 == steps % 2
+    }
+
+    /// The number of plots that can be stood on after exactly `steps` steps.
+    pub fn reachable(&self, steps: u64) -> usize {
+        let furthest = self.furthest();
+        if steps <= furthest {
+            self.reachable[steps as usize]
+        } else if (steps - furthest).is_multiple_of(2) {
+            self.reachable[furthest as usize]
+        } else {
+            furthest
+                .checked_sub(1)
+                .map_or(0, |d| self.reachable[d as usize])
+        }
+    }
+
+    /// The distance to the furthest plot. Past this the count only alternates with parity.
+    pub fn furthest(&self) -> u64 {
+        (self.reachable.len() - 1) as u64
     }
 
-    positions.extend(new_positions)
+    pub fn is_reachable(&self, (x, y): (usize, usize), steps: u64) -> bool {
+        self.distances[y * self.width + x].is_some_and(|d| Self::within(d, steps))
+    }
 }
 
-fn step_infinite(
-    input: &[Vec<u8>],
-    positions: &mut Vec<(isize, isize)>,
-    new_positions: &mut HashSet<(isize, isize)>,
-) {
-    let y_len = input.len();
-    let x_len = input[0].len();
-
-    for (x, y) in positions.drain(..) {
-        for (x, y) in adjacent_positions_infinite((x, y)) {
-            let inp =
-                input[y.rem_euclid(y_len as isize) as usize][x.rem_euclid(x_len as isize) as usize];
-            if inp == b'.' || inp == b'S' {
-                new_positions.insert((x, y));
-            }
+/// Plots reachable after `steps` steps in the infinitely repeating garden.
+///
+/// After the first `steps % width` steps, every further `width` steps the reachable area grows
+/// by one ring of garden copies, so the counts at those checkpoints follow a quadratic. The
+/// first three checkpoints fit it and the fourth confirms it.
+fn infinite_reachable(input: &[Vec<u8>], steps: u64) -> anyhow::Result<u64> {
+    let width = input.len() as u64;
+    if input.iter().any(|row| row.len() as u64 != width) {
+        return Err(anyhow!("The garden must be square"));
+    }
+
+    let map = DistanceMap::new(input, 9);
+    let checkpoint = |k: u64| -> anyhow::Result<u64> {
+        let steps = steps % width + k * width;
+        if steps >= map.radius as u64 {
+            return Err(anyhow!("{} steps walk off the distance map", steps));
         }
+        Ok(map.reachable(steps) as u64)
+    };
+    let counts = (0..4).map(checkpoint).collect::<anyhow::Result<Vec<_>>>()?;
+
+    let n = steps / width;
+    if n < 4 {
+        return Ok(counts[n as usize]);
+    }
+
+    let first = counts[1] as i128 - counts[0] as i128;
+    let second = counts[2] as i128 - 2 * counts[1] as i128 + counts[0] as i128;
+    let check = counts[3] as i128 - 2 * counts[2] as i128 + counts[1] as i128;
+    if second != check {
+        return Err(anyhow!(
+            "Reachable plots do not grow quadratically (second differences {} and {})",
+            second,
+            check
+        ));
+    }
+
+    let n = n as i128;
+    let count = counts[0] as i128 + n * first + n * (n - 1) / 2 * second;
+    u64::try_from(count).map_err(|_| anyhow!("{} does not fit in a u64", count))
+}
+
+/// Records the plots reachable after each step up to `max_steps` to `--csv` and `--render`.
+fn record_growth(args: &Args, map: &DistanceMap, max_steps: u64) -> anyhow::Result<()> {
+    if args.csv.is_none() && args.render.is_none() {
+        return Ok(());
     }
 
-    positions.extend(new_positions.iter());
-    new_positions.clear();
+    let mut series = Series::new("steps", "reachable plots", args.differences);
+    for steps in 0..=max_steps {
+        series.record(steps as i64, map.reachable(steps) as i64);
+    }
+    if let Some(path) = &args.csv {
+        series.write_csv(path)?;
+    }
+    if let Some(path) = &args.render {
+        render_to_file(path, &series)?;
+    }
+    Ok(())
 }
 
-fn debug(input: &[Vec<u8>], positions: &[(usize, usize)]) {
+fn debug(input: &[Vec<u8>], map: &DistanceMap, steps: u64) {
     for (y, row) in input.iter().enumerate() {
         for (x, cell) in row.iter().enumerate() {
-            if positions.contains(&(x, y)) {
+            if map.is_reachable((x, y), steps) {
                 print!("O");
             } else {
                 print!("{}", *cell as char);
@@ -99,35 +228,39 @@ fn debug(input: &[Vec<u8>], positions: &[(usize, usize)]) {
     }
 }
 
-pub fn part_one(_args: Args) {
+pub fn part_one(args: Args) {
     let input = input();
-    let starting_point = starting_point(&input);
-    let mut positions = vec![starting_point];
+    let steps = args.steps.unwrap_or(64);
+    let map = DistanceMap::new(&input, 1);
 
-    for _ in 0..64 {
-        step(&input, &mut positions);
-    }
-
-    debug(&input, &positions);
-    println!("Number of available spots: {}", positions.len());
+    debug(&input, &map, steps);
+    record_growth(&args, &map, steps.min(map.furthest() + 1)).unwrap();
+    println!("Number of available spots: {}", map.reachable(steps));
 }
 
-pub fn part_two(_args: Args) {
+pub fn part_two(args: Args) {
     let input = input();
-    let starting_point = starting_point(&input);
-    let mut positions = Vec::with_capacity(1024 * 1024 * 32);
-    positions.push((starting_point.0 as _, starting_point.1 as _));
-    let mut new_positions = HashSet::with_capacity(1024 * 1024 * 32);
-    let mut csv = std::fs::File::create("day_21.csv").unwrap();
-
-    for i in 0..26501365 {
-        if i % 100 == 0 {
-            println!("Iteration: {}", i);
+    let steps = args.steps.unwrap_or(26501365);
+
+    if args.brute_force {
+        let width = input.len() as u64;
+        let map = DistanceMap::new(&input, 9);
+        for steps in (0..4).map(|k| steps % width + k * width) {
+            let expected = brute_force(&input, steps);
+            let actual = map.reachable(steps);
+            println!(
+                "{} steps: distance map {}, brute force {}",
+                steps, actual, expected
+            );
+            assert_eq!(actual, expected, "Distance map disagrees with brute force");
         }
-        step_infinite(&input, &mut positions, &mut new_positions);
-        csv.write(format!("{},{}\n", i + 1, positions.len()).as_bytes())
-            .unwrap();
     }
 
-    println!("Number of available spots: {}", positions.len());
+    if args.csv.is_some() || args.render.is_some() {
+        let map = DistanceMap::new(&input, 9);
+        record_growth(&args, &map, map.radius as u64 - 1).unwrap();
+    }
+
+    let count = infinite_reachable(&input, steps).unwrap();
+    println!("Number of available spots: {}", count);
 }
@@ -1,19 +1,38 @@
-use plotters::prelude::*;
-use std::{num::ParseIntError, str::FromStr};
+use plotters::{coord::Shift, prelude::*};
+use std::{fmt::Display, num::ParseIntError, str::FromStr};
 
 use itertools::Itertools;
-use plotters::{chart::ChartBuilder, drawing::IntoDrawingArea};
 
-use crate::Args;
+use crate::{
+    render::{render_to_file, Drawing},
+    Args,
+};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_eighteen.txt");
 
 struct PlanEntry {
     direction: Direction,
-    meters: u16,
+    meters: u32,
     color: (u32, u16),
 }
 
+/// A single trench, however it was written in the plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Move {
+    direction: Direction,
+    meters: i64,
+}
+
+impl Move {
+    pub fn apply(&self, position: (i64, i64)) -> (i64, i64) {
+        let forward = self.direction.forward();
+        (
+            position.0 + forward.0 as i64 * self.meters,
+            position.1 + forward.1 as i64 * self.meters,
+        )
+    }
+}
+
 impl FromStr for PlanEntry {
     type Err = anyhow::Error;
 
@@ -22,6 +41,9 @@ impl FromStr for PlanEntry {
             .split_whitespace()
             .collect_tuple()
             .ok_or_else(|| anyhow!("Input is malformed"))?;
+        if color.len() != 9 || !color.starts_with("(#") || !color.ends_with(')') {
+            return Err(anyhow!("Color {:?} is not of the form (#rrggbb)", color));
+        }
 
         Ok(PlanEntry {
             direction: direction.parse()?,
@@ -34,6 +56,60 @@ impl FromStr for PlanEntry {
     }
 }
 
+impl PlanEntry {
+    /// Reads the entry as part one does, from the direction letter and meters.
+    pub fn part_one_move(&self) -> anyhow::Result<Move> {
+        Ok(Move {
+            direction: self.direction,
+            meters: self.meters as i64,
+        })
+    }
+
+    /// Reads the entry as part two does, from the five hex digits of distance followed by
+    /// one digit of direction.
+    pub fn part_two_move(&self) -> anyhow::Result<Move> {
+        let direction = match self.color.1 {
+            0 => Direction::East,
+            1 => Direction::South,
+            2 => Direction::West,
+            3 => Direction::North,
+            n => return Err(anyhow!("Unknown direction digit {}", n)),
+        };
+        Ok(Move {
+            direction,
+            meters: self.color.0 as i64,
+        })
+    }
+
+    /// Writes an entry that digs `movement` under both the part one and part two readings.
+    pub fn encode(movement: Move) -> anyhow::Result<Self> {
+        if !(0..=0xFFFFF).contains(&movement.meters) {
+            return Err(anyhow!(
+                "{} meters does not fit in five hex digits",
+                movement.meters
+            ));
+        }
+        let direction_digit = match movement.direction {
+            Direction::East => 0,
+            Direction::South => 1,
+            Direction::West => 2,
+            Direction::North => 3,
+        };
+        Ok(Self {
+            direction: movement.direction,
+            meters: movement.meters as u32,
+            color: (movement.meters as u32, direction_digit),
+        })
+    }
+
+    /// The trench color from the part one reading of the hex code.
+    pub fn rgb(&self) -> RGBColor {
+        let hex = (self.color.0 << 4) | self.color.1 as u32;
+        RGBColor((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
     North,
     East,
@@ -52,6 +128,22 @@ impl Direction {
     }
 }
 
+impl Display for PlanEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let letter = match self.direction {
+            Direction::East => 'R',
+            Direction::West => 'L',
+            Direction::North => 'U',
+            Direction::South => 'D',
+        };
+        write!(
+            f,
+            "{} {} (#{:05x}{:x})",
+            letter, self.meters, self.color.0, self.color.1
+        )
+    }
+}
+
 impl FromStr for Direction {
     type Err = anyhow::Error;
 
@@ -66,107 +158,265 @@ impl FromStr for Direction {
     }
 }
 
+/// Length of the longest side of the rendered image, in pixels.
+const RENDER_SIZE: u32 = 1000;
+
 struct Lake {
     points: Vec<(i64, i64)>,
+    /// The color of the trench leading up to each point after the first.
+    colors: Vec<RGBColor>,
 }
 
 impl Lake {
     pub fn new() -> Self {
-        Self { points: vec![] }
+        Self {
+            points: vec![],
+            colors: vec![],
+        }
     }
 
-    pub fn add_point(&mut self, x: i64, y: i64) {
+    pub fn add_point(&mut self, x: i64, y: i64, color: RGBColor) {
+        if !self.points.is_empty() {
+            self.colors.push(color);
+        }
         self.points.push((x, y));
     }
 
-    pub fn draw(&self) {
-        let backend = SVGBackend::new("visual.svg", (500, 500));
-        let area = backend.into_drawing_area();
-        area.fill(&WHITE).unwrap();
-        let mut chart = ChartBuilder::on(&area)
-            .build_cartesian_2d(
-                (self.points.iter().map(|p| p.0 as i64).min().unwrap() - 1)
-                    ..(self.points.iter().map(|p| p.0).max().unwrap() + 1),
-                (self.points.iter().map(|p| p.1).min().unwrap() - 1)
-                    ..(self.points.iter().map(|p| p.1).max().unwrap() + 1),
-            )
-            .unwrap();
-        chart
-            .draw_series(LineSeries::new(
-                self.points.iter().map(|l| (l.0, l.1)),
-                RED.filled(),
-            ))
-            .unwrap();
-        chart.configure_mesh().draw().unwrap();
-        area.present().unwrap();
-    }
-
-    pub fn area(&self) -> f64 {
-        let mut area = 0;
-        for (left, right) in self.points.iter().tuple_windows() {
-            let (x1, y1) = left;
-            let (x2, y2) = right;
-            area += (y1 * x2) - (x1 * y2);
-        }
-
-        let perimeter = self
+    fn bounds(&self) -> ((i64, i64), (i64, i64)) {
+        let (min_x, max_x) = self
+            .points
+            .iter()
+            .map(|p| p.0)
+            .minmax()
+            .into_option()
+            .unwrap_or((0, 0));
+        let (min_y, max_y) = self
             .points
             .iter()
+            .map(|p| p.1)
+            .minmax()
+            .into_option()
+            .unwrap_or((0, 0));
+        ((min_x - 1, max_x + 1), (min_y - 1, max_y + 1))
+    }
+
+    /// The trench segments, from one corner to the next.
+    fn edges(&self) -> impl Iterator<Item = ((i128, i128), (i128, i128))> + '_ {
+        self.points
+            .iter()
+            .map(|&(x, y)| (x as i128, y as i128))
             .tuple_windows()
-            .fold(0, |acc, (left, right)| {
-                acc + ((left.0 - right.0) + (left.1 - right.1)).abs()
-            });
+    }
 
-        ((area / 2).abs() as f64 - (0.5 * perimeter as f64) + 1.) + perimeter as f64
+    /// Checks that the trench is a closed, non-self-intersecting loop of horizontal and
+    /// vertical segments, which every other measurement relies on.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.points.len() < 5 {
+            return Err(anyhow!("A lagoon needs at least four trenches"));
+        }
+        if self.points.first() != self.points.last() {
+            return Err(anyhow!(
+                "The trench ends at {:?} instead of returning to {:?}",
+                self.points.last().unwrap(),
+                self.points.first().unwrap()
+            ));
+        }
+
+        let edges = self.edges().collect_vec();
+        for (i, (from, to)) in edges.iter().enumerate() {
+            if from == to {
+                return Err(anyhow!("Trench {} at {:?} has no length", i + 1, from));
+            }
+            if from.0 != to.0 && from.1 != to.1 {
+                return Err(anyhow!(
+                    "Trench {} from {:?} to {:?} is not horizontal or vertical",
+                    i + 1,
+                    from,
+                    to
+                ));
+            }
+        }
+
+        for (i, j) in (0..edges.len()).tuple_combinations() {
+            let adjacent = j == i + 1 || (i == 0 && j == edges.len() - 1);
+            let Some(((min_x, max_x), (min_y, max_y))) = overlap(edges[i], edges[j]) else {
+                continue;
+            };
+            // Neighbouring trenches meet at a corner, anything more means they double back.
+            if !adjacent || min_x != max_x || min_y != max_y {
+                return Err(anyhow!(
+                    "Trench {} crosses trench {} near ({}, {})",
+                    i + 1,
+                    j + 1,
+                    min_x,
+                    min_y
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The area enclosed by the centers of the trench cells, from the shoelace formula.
+    pub fn area(&self) -> i128 {
+        let twice_area = self
+            .edges()
+            .map(|((x1, y1), (x2, y2))| (y1 * x2) - (x1 * y2))
+            .sum::<i128>();
+        twice_area.abs() / 2
+    }
+
+    /// The number of cubes dug out for the trench itself.
+    pub fn boundary_points(&self) -> i128 {
+        self.edges()
+            .map(|((x1, y1), (x2, y2))| (x2 - x1).abs() + (y2 - y1).abs())
+            .sum()
     }
+
+    /// The number of cubes strictly inside the trench, from Pick's theorem.
+    pub fn interior_points(&self) -> i128 {
+        self.area() - self.boundary_points() / 2 + 1
+    }
+
+    /// How many cubic meters of lava the lagoon holds.
+    pub fn volume(&self) -> i128 {
+        self.interior_points() + self.boundary_points()
+    }
+}
+
+type Edge = ((i128, i128), (i128, i128));
+
+/// The box two axis-aligned segments share, if they touch at all.
+fn overlap(a: Edge, b: Edge) -> Option<((i128, i128), (i128, i128))> {
+    let range = |from: i128, to: i128| (from.min(to), from.max(to));
+    let shared = |(a_min, a_max): (i128, i128), (b_min, b_max): (i128, i128)| {
+        let (min, max) = (a_min.max(b_min), a_max.min(b_max));
+        (min <= max).then_some((min, max))
+    };
+    Some((
+        shared(range(a.0 .0, a.1 .0), range(b.0 .0, b.1 .0))?,
+        shared(range(a.0 .1, a.1 .1), range(b.0 .1, b.1 .1))?,
+    ))
 }
 
-fn input() -> Vec<PlanEntry> {
-    FILE_CONTENTS
+impl Drawing for Lake {
+    /// Scales the image so the lagoon keeps its aspect ratio.
+    fn size(&self) -> (u32, u32) {
+        let ((min_x, max_x), (min_y, max_y)) = self.bounds();
+        let (width, height) = ((max_x - min_x) as f64, (max_y - min_y) as f64);
+        let scale = RENDER_SIZE as f64 / width.max(height);
+        (
+            ((width * scale) as u32).max(100),
+            ((height * scale) as u32).max(100),
+        )
+    }
+
+    fn draw<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>) -> anyhow::Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        let ((min_x, max_x), (min_y, max_y)) = self.bounds();
+        area.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(area)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(min_x..max_x, min_y..max_y)?;
+        chart.configure_mesh().draw()?;
+
+        chart.draw_series(std::iter::once(Polygon::new(
+            self.points.clone(),
+            RGBColor(173, 216, 230).filled(),
+        )))?;
+        for ((from, to), color) in self.points.iter().tuple_windows().zip(&self.colors) {
+            chart.draw_series(LineSeries::new([*from, *to], color.stroke_width(3)))?;
+        }
+        Ok(())
+    }
+}
+
+fn input(args: &Args) -> anyhow::Result<Vec<PlanEntry>> {
+    let contents = match &args.input {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => FILE_CONTENTS.to_owned(),
+    };
+    contents
         .lines()
-        .map(|l| l.parse().unwrap())
+        .enumerate()
+        .map(|(i, l)| {
+            l.parse()
+                .map_err(|e: anyhow::Error| e.context(format!("Line {}", i + 1)))
+        })
+        .collect()
+}
+
+/// Merges consecutive moves in the same direction and drops moves that go nowhere.
+fn normalize(moves: &[Move]) -> Vec<Move> {
+    moves
+        .iter()
+        .filter(|m| m.meters != 0)
+        .copied()
+        .coalesce(|previous, next| {
+            if previous.direction == next.direction {
+                Ok(Move {
+                    direction: previous.direction,
+                    meters: previous.meters + next.meters,
+                })
+            } else {
+                Err((previous, next))
+            }
+        })
         .collect_vec()
 }
 
-fn plan(calculate: fn(PlanEntry, (i64, i64)) -> (i64, i64)) {
-    let input = input();
+/// Digs out the lagoon, coloring each trench with `color` if one is given or the
+/// color from its plan entry otherwise.
+fn plan(
+    args: &Args,
+    color: Option<RGBColor>,
+    decode: fn(&PlanEntry) -> anyhow::Result<Move>,
+) -> anyhow::Result<()> {
+    let input = input(args)?;
+    let moves = input
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| decode(entry).map_err(|e| e.context(format!("Line {}", i + 1))))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if args.convert {
+        for movement in normalize(&moves) {
+            println!("{}", PlanEntry::encode(movement)?);
+        }
+        return Ok(());
+    }
+
     let mut grid = Lake::new();
     let start = (0, 0);
-    grid.add_point(start.0, start.1);
+    grid.add_point(start.0, start.1, BLACK);
     let mut position = (start.0, start.1);
 
-    for entry in input {
-        let (x, y) = calculate(entry, position);
-        grid.add_point(x, y);
+    for (entry, movement) in input.iter().zip(moves) {
+        let trench_color = color.unwrap_or_else(|| entry.rgb());
+        let (x, y) = movement.apply(position);
+        grid.add_point(x, y, trench_color);
         position = (x, y);
     }
-    grid.add_point(start.0, start.1);
-    grid.draw();
 
-    println!("Area: {}", grid.area());
+    grid.validate()?;
+
+    if let Some(path) = &args.render {
+        render_to_file(path, &grid)?;
+    }
+
+    println!("Boundary points: {}", grid.boundary_points());
+    println!("Interior points: {}", grid.interior_points());
+    println!("Area: {}", grid.volume());
+    Ok(())
 }
 
-pub fn part_one(_args: Args) {
-    plan(|entry, position| {
-        let forward = entry.direction.forward();
-        let x = position.0 + (forward.0 * entry.meters as i32) as i64;
-        let y = position.1 + (forward.1 * entry.meters as i32) as i64;
-        return (x, y);
-    })
+pub fn part_one(args: Args) {
+    plan(&args, None, PlanEntry::part_one_move).unwrap()
 }
-pub fn part_two(_args: Args) {
-    plan(|entry, position| {
-        let distance = entry.color.0 as i64;
-        let direction = match entry.color.1 {
-            0 => Direction::East,
-            1 => Direction::South,
-            2 => Direction::West,
-            3 => Direction::North,
-            _ => unreachable!(),
-        };
-        let forward = direction.forward();
-        let x = position.0 + (forward.0 as i64 * distance) as i64;
-        let y = position.1 + (forward.1 as i64 * distance) as i64;
-        return (x, y);
-    })
+pub fn part_two(args: Args) {
+    plan(&args, Some(RED), PlanEntry::part_two_move).unwrap()
 }
@@ -1,4 +1,11 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    hash::Hasher,
+    io::{self, BufRead, BufReader},
+    str::FromStr,
+};
 
 use itertools::Itertools;
 
@@ -6,92 +13,292 @@ use crate::Args;
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_fifteen.txt");
 
-fn input() -> Vec<&'static str> {
-    FILE_CONTENTS.split(',').collect_vec()
+/// Opens the initialization sequence, either from `--input` or the bundled puzzle input.
+fn input(args: &Args) -> io::Result<Box<dyn BufRead>> {
+    Ok(match &args.input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(FILE_CONTENTS.as_bytes()),
+    })
+}
+
+/// The HASH algorithm from the puzzle, fed one chunk of bytes at a time.
+#[derive(Debug, Default, Clone, Copy)]
+struct HolidayHasher {
+    current: u8,
+}
+
+impl HolidayHasher {
+    pub fn value(&self) -> u8 {
+        self.current
+    }
+}
+
+impl Hasher for HolidayHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.current = self.current.wrapping_add(*byte).wrapping_mul(17);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.current as u64
+    }
 }
 
 fn hash(inp: &str) -> u8 {
-    let mut hash_store = 0_u64;
+    let mut hasher = HolidayHasher::default();
+    hasher.write(inp.as_bytes());
+    hasher.value()
+}
 
-    for byte in inp.bytes() {
-        hash_store += byte as u64;
-        hash_store *= 17;
-        hash_store %= 256;
+fn is_newline(byte: u8) -> bool {
+    byte == b'\n' || byte == b'\r'
+}
+
+/// Sums the HASH of every step without holding more than the reader's buffer in memory.
+fn sum_of_hashes<R: BufRead>(mut reader: R) -> io::Result<u64> {
+    let mut sum = 0;
+    let mut hasher = HolidayHasher::default();
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        for &byte in buf {
+            match byte {
+                b',' => {
+                    sum += hasher.value() as u64;
+                    hasher = HolidayHasher::default();
+                }
+                byte if is_newline(byte) => {}
+                byte => hasher.write_u8(byte),
+            }
+        }
+        let len = buf.len();
+        reader.consume(len);
     }
+    Ok(sum + hasher.value() as u64)
+}
 
-    hash_store as u8
+/// Reads comma separated steps one at a time, ignoring newlines as the puzzle asks.
+struct Steps<R> {
+    reader: R,
+    done: bool,
 }
 
-pub fn part_one(_args: Args) {
-    let input = input();
-    let sum = input.iter().map(|&s| hash(s) as u64).sum::<u64>();
-    println!("Sum: {}", sum);
+impl<R: BufRead> Steps<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+        }
+    }
 }
-pub fn part_two(_args: Args) {
-    let input = input();
-    let mut boxes = HashMap::<u8, Vec<(&str, u8)>>::new();
 
-    for step in input {
-        let (label, lens) = if step.contains('-') {
-            (step.split('-').next().unwrap(), None)
+impl<R: BufRead> Iterator for Steps<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut step = vec![];
+        if let Err(e) = self.reader.read_until(b',', &mut step) {
+            self.done = true;
+            return Some(Err(e));
+        }
+        if step.last() == Some(&b',') {
+            step.pop();
         } else {
-            let (label, right) = step.split('=').collect_tuple::<(&str, &str)>().unwrap();
-            (label, Some(right.parse::<u8>().unwrap()))
-        };
-        let hash = hash(label);
+            self.done = true;
+        }
+        step.retain(|b| !is_newline(*b));
+        if self.done && step.is_empty() {
+            return None;
+        }
 
-        if lens.is_none() {
-            if let Some(prev) = boxes.get_mut(&hash) {
-                if let Some(index) = prev
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(i, (l, _v))| {
-                        if *l == label {
-                            return Some(i);
-                        }
-                        None
-                    })
-                    .next()
-                {
-                    prev.remove(index);
+        Some(String::from_utf8(step).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Insert { label: String, focal_length: u8 },
+    Remove { label: String },
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (label, step) = if let Some(label) = s.strip_suffix('-') {
+            (
+                label,
+                Step::Remove {
+                    label: label.to_owned(),
+                },
+            )
+        } else if let Some((label, focal_length)) = s.split_once('=') {
+            let focal_length = match focal_length.parse::<u8>() {
+                Ok(n @ 1..=9) => n,
+                _ => {
+                    return Err(anyhow!(
+                        "Focal length in {:?} must be a digit from 1 to 9",
+                        s
+                    ))
                 }
+            };
+            (
+                label,
+                Step::Insert {
+                    label: label.to_owned(),
+                    focal_length,
+                },
+            )
+        } else {
+            return Err(anyhow!("Step {:?} is neither label=N nor label-", s));
+        };
+
+        if label.is_empty() || !label.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(anyhow!("Step {:?} has an invalid label", s));
+        }
+        Ok(step)
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Insert {
+                label,
+                focal_length,
+            } => write!(f, "{}={}", label, focal_length),
+            Step::Remove { label } => write!(f, "{}-", label),
+        }
+    }
+}
+
+/// The 256 boxes of the HASHMAP procedure.
+///
+/// Removed lenses leave an empty slot behind so that the remaining lenses keep their
+/// positions in `index`. A box is compacted and re-indexed once its empty slots outnumber its
+/// lenses, so steps take amortized O(1) time and memory stays proportional to the lenses
+/// currently in the boxes.
+#[derive(Debug, Clone)]
+struct LensBoxes {
+    boxes: Vec<Vec<Option<(String, u8)>>>,
+    /// Number of lenses, rather than slots, in each box.
+    live: Vec<usize>,
+    /// Slot of every lens currently in a box, keyed by label.
+    index: HashMap<String, usize>,
+}
+
+impl LensBoxes {
+    pub fn new() -> Self {
+        Self {
+            boxes: vec![vec![]; 256],
+            live: vec![0; 256],
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, label: &str, focal_length: u8) {
+        let box_number = hash(label) as usize;
+        let lenses = &mut self.boxes[box_number];
+        match self.index.get(label) {
+            Some(&slot) => lenses[slot] = Some((label.to_owned(), focal_length)),
+            None => {
+                self.live[box_number] += 1;
+                self.index.insert(label.to_owned(), lenses.len());
+                lenses.push(Some((label.to_owned(), focal_length)));
             }
-            continue;
-        }
-
-        let lens = lens.unwrap();
-        if let Some(prev) = boxes.get_mut(&hash) {
-            if let Some(idx) = prev
-                .iter()
-                .enumerate()
-                .filter_map(|(i, (l, _))| {
-                    if *l == label {
-                        return Some(i);
-                    }
-                    None
-                })
-                .next()
-            {
-                prev[idx] = (label, lens);
-            } else {
-                prev.push((label, lens));
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<u8> {
+        let slot = self.index.remove(label)?;
+        let box_number = hash(label) as usize;
+        let (_, focal_length) = self.boxes[box_number][slot].take()?;
+        self.live[box_number] -= 1;
+        if self.boxes[box_number].len() > 2 * self.live[box_number] {
+            self.compact(box_number);
+        }
+        Some(focal_length)
+    }
+
+    /// Drops the empty slots from a box and moves its lenses' entries in `index` to match.
+    fn compact(&mut self, box_number: usize) {
+        let lenses = &mut self.boxes[box_number];
+        lenses.retain(|l| l.is_some());
+        for (slot, (label, _)) in lenses.iter().flatten().enumerate() {
+            *self.index.get_mut(label).unwrap() = slot;
+        }
+    }
+
+    pub fn apply(&mut self, step: &Step) {
+        match step {
+            Step::Insert {
+                label,
+                focal_length,
+            } => self.insert(label, *focal_length),
+            Step::Remove { label } => {
+                self.remove(label);
             }
-        } else {
-            boxes.insert(hash, vec![(label, lens)]);
         }
     }
 
-    println!("{:#?}", boxes);
+    /// The lenses in a box, front to back.
+    pub fn lenses(&self, box_number: u8) -> impl Iterator<Item = (&str, u8)> {
+        self.boxes[box_number as usize]
+            .iter()
+            .flatten()
+            .map(|(label, focal_length)| (label.as_str(), *focal_length))
+    }
 
-    let sum = boxes
-        .into_iter()
-        .map(|(key, val)| {
-            val.iter()
-                .enumerate()
-                .map(|(j, (_, val))| (key as usize + 1) * ((j + 1) * (*val as usize)))
-                .sum::<usize>()
-        })
-        .sum::<usize>();
+    pub fn focusing_power(&self) -> usize {
+        (0..=255)
+            .map(|box_number| {
+                self.lenses(box_number)
+                    .enumerate()
+                    .map(|(slot, (_, focal_length))| {
+                        (box_number as usize + 1) * (slot + 1) * focal_length as usize
+                    })
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+}
 
+impl Display for LensBoxes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for box_number in 0..=255 {
+            let lenses = self
+                .lenses(box_number)
+                .map(|(label, focal_length)| format!("[{} {}]", label, focal_length))
+                .join(" ");
+            if !lenses.is_empty() {
+                writeln!(f, "Box {}: {}", box_number, lenses)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn part_one(args: Args) {
+    let sum = sum_of_hashes(input(&args).unwrap()).unwrap();
     println!("Sum: {}", sum);
 }
+
+pub fn part_two(args: Args) {
+    let mut boxes = LensBoxes::new();
+    for step in Steps::new(input(&args).unwrap()) {
+        let step: Step = step.unwrap().parse().unwrap();
+        boxes.apply(&step);
+        if args.trace {
+            println!("After \"{}\":\n{}", step, boxes);
+        }
+    }
+
+    println!("Sum: {}", boxes.focusing_power());
+}
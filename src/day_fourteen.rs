@@ -1,207 +1,321 @@
-use std::collections::{hash_map::DefaultHasher, HashMap};
+use std::{collections::HashMap, fmt::Display, path::Path, str::FromStr};
 
-use itertools::Itertools;
+use plotters::{coord::Shift, prelude::*};
 
-use crate::Args;
+use crate::{
+    bit_grid::{span, BitGrid},
+    Args,
+};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_fourteen.txt");
 
-fn input() -> Vec<Vec<u8>> {
-    FILE_CONTENTS
-        .lines()
-        .map(|l| l.bytes().collect_vec())
-        .collect_vec()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    North,
+    West,
+    South,
+    East,
 }
 
-fn roll_north(dish: &mut Vec<Vec<u8>>) {
-    let row_len = dish.len();
-    for row_idx in 0..row_len {
-        let col_len = dish[row_idx].len();
-        for col_idx in 0..col_len {
-            let byte = dish[row_idx][col_idx];
-            if byte == b'O' {
-                let idx = get_northmost_index(dish.as_slice(), (row_idx, col_idx));
-                dish[row_idx][col_idx] = b'.';
-                dish[idx][col_idx] = b'O';
-            }
+/// The order the dish is tilted in during a single spin cycle.
+const SPIN_CYCLE: [Direction; 4] = [
+    Direction::North,
+    Direction::West,
+    Direction::South,
+    Direction::East,
+];
+
+#[derive(Debug, Clone)]
+struct Dish {
+    rocks: BitGrid,
+    walls: BitGrid,
+    /// The walls with rows and columns swapped, so north and south tilts can work on rows.
+    transposed_walls: BitGrid,
+}
+
+impl FromStr for Dish {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(byte) = s.bytes().find(|b| !b".#O\n".contains(b)) {
+            return Err(anyhow!("Unknown tile {:?}", byte as char));
         }
+
+        let walls = BitGrid::parse(s, b'#')?;
+        Ok(Self {
+            rocks: BitGrid::parse(s, b'O')?,
+            transposed_walls: walls.transpose(),
+            walls,
+        })
     }
 }
 
-fn roll_south(dish: &mut Vec<Vec<u8>>) {
-    let row_len = dish.len();
-    for row_idx in (0..row_len).rev() {
-        let col_len = dish[row_idx].len();
-        for col_idx in 0..col_len {
-            let byte = dish[row_idx][col_idx];
-            if byte == b'O' {
-                let idx = get_southmost_index(dish.as_slice(), (row_idx, col_idx));
-                dish[row_idx][col_idx] = b'.';
-                dish[idx][col_idx] = b'O';
+impl Display for Dish {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for y in 0..self.rocks.height() {
+            for x in 0..self.rocks.width() {
+                let tile = match (self.rocks.get(x, y), self.walls.get(x, y)) {
+                    (true, _) => 'O',
+                    (_, true) => '#',
+                    _ => '.',
+                };
+                write!(f, "{}", tile)?;
             }
+            writeln!(f)?;
         }
+        Ok(())
     }
 }
 
-fn roll_east(dish: &mut Vec<Vec<u8>>) {
-    let row_len = dish.len();
-    for row_idx in 0..row_len {
-        let col_len = dish[row_idx].len();
-        for col_idx in (0..col_len).rev() {
-            let byte = dish[row_idx][col_idx];
-            if byte == b'O' {
-                let idx = get_eastmost_index(dish.as_slice(), (row_idx, col_idx));
-                dish[row_idx][col_idx] = b'.';
-                dish[row_idx][idx] = b'O';
-            }
-        }
+/// Slides every rock in a line toward bit 0 (or the highest bit) in one pass, filling
+/// each run between walls from its next free slot.
+fn tilt_line(rocks: u128, walls: u128, width: usize, toward_start: bool) -> u128 {
+    let mut tilted = 0;
+    let mut start = 0;
+    while start < width {
+        let walls_ahead = walls & !span(0, start);
+        let end = match walls_ahead {
+            0 => width,
+            _ => walls_ahead.trailing_zeros() as usize,
+        };
+        let count = (rocks & span(start, end - start)).count_ones() as usize;
+        let next_free = if toward_start { start } else { end - count };
+        tilted |= span(next_free, count);
+        start = end + 1;
     }
+    tilted
 }
 
-fn roll_west(dish: &mut Vec<Vec<u8>>) {
-    let row_len = dish.len();
-    for row_idx in 0..row_len {
-        let col_len = dish[row_idx].len();
-        for col_idx in 0..col_len {
-            let byte = dish[row_idx][col_idx];
-            if byte == b'O' {
-                let idx = get_westmost_index(dish.as_slice(), (row_idx, col_idx));
-                dish[row_idx][col_idx] = b'.';
-                dish[row_idx][idx] = b'O';
-            }
-        }
+fn tilt_lines(lines: &mut BitGrid, walls: &BitGrid, toward_start: bool) {
+    let width = lines.width();
+    for (line, walls) in lines.rows_mut().iter_mut().zip(walls.rows()) {
+        *line = tilt_line(*line, *walls, width, toward_start);
     }
 }
 
-fn get_northmost_index(iter: &[Vec<u8>], starting_index: (usize, usize)) -> usize {
-    let (start_row, start_col) = starting_index;
+impl Dish {
+    pub fn tilt(&mut self, direction: Direction) {
+        match direction {
+            Direction::West | Direction::East => {
+                tilt_lines(&mut self.rocks, &self.walls, direction == Direction::West)
+            }
+            Direction::North | Direction::South => {
+                let mut columns = self.rocks.transpose();
+                tilt_lines(
+                    &mut columns,
+                    &self.transposed_walls,
+                    direction == Direction::North,
+                );
+                self.rocks = columns.transpose();
+            }
+        }
+    }
 
-    for i in (0..start_row).rev() {
-        let byte = iter[i][start_col];
-        if byte == b'#' || byte == b'O' {
-            return i + 1;
+    pub fn spin_cycle(&mut self) {
+        for direction in SPIN_CYCLE {
+            self.tilt(direction);
         }
     }
 
-    return 0;
+    pub fn north_load(&self) -> usize {
+        let len = self.rocks.height();
+        self.rocks
+            .rows()
+            .iter()
+            .enumerate()
+            .map(|(i, row)| row.count_ones() as usize * (len - i))
+            .sum::<usize>()
+    }
+}
+
+/// The spin cycle after which the dish first returns to an earlier state, and how many
+/// cycles it takes to come back around.
+#[derive(Debug, Clone, Copy)]
+struct Repetition {
+    start: usize,
+    period: usize,
 }
 
-fn get_eastmost_index(iter: &[Vec<u8>], starting_index: (usize, usize)) -> usize {
-    let (start_row, start_col) = starting_index;
-    let len = iter[0].len();
-    for i in start_col + 1..len {
-        let byte = iter[start_row][i];
-        if byte == b'#' || byte == b'O' {
-            return i - 1;
+fn find_repetition(dish: &Dish) -> Repetition {
+    let mut dish = dish.clone();
+    let mut seen = HashMap::new();
+    seen.insert(dish.rocks.clone(), 0);
+
+    for cycle in 1.. {
+        dish.spin_cycle();
+        if let Some(start) = seen.insert(dish.rocks.clone(), cycle) {
+            return Repetition {
+                start,
+                period: cycle - start,
+            };
         }
     }
+    unreachable!()
+}
 
-    return len - 1;
+struct Frame {
+    cycle: usize,
+    tilted: Option<Direction>,
+    dish: Dish,
 }
 
-fn get_westmost_index(iter: &[Vec<u8>], starting_index: (usize, usize)) -> usize {
-    let (start_row, start_col) = starting_index;
-    for i in (0..start_col).rev() {
-        let byte = iter[start_row][i];
-        if byte == b'#' || byte == b'O' {
-            return i + 1;
+fn record_frames(dish: &Dish, cycles: usize) -> Vec<Frame> {
+    let mut dish = dish.clone();
+    let mut frames = vec![Frame {
+        cycle: 0,
+        tilted: None,
+        dish: dish.clone(),
+    }];
+    for cycle in 1..=cycles {
+        for direction in SPIN_CYCLE {
+            dish.tilt(direction);
+            frames.push(Frame {
+                cycle,
+                tilted: Some(direction),
+                dish: dish.clone(),
+            });
         }
     }
-
-    return 0;
+    frames
 }
 
-fn get_southmost_index(iter: &[Vec<u8>], starting_index: (usize, usize)) -> usize {
-    let (start_row, start_col) = starting_index;
+const CELL_SIZE: u32 = 6;
+const HEADER_SIZE: u32 = 50;
 
-    let len = iter.len();
-    for i in start_row + 1..len {
-        let byte = iter[i][start_col];
-        if byte == b'#' || byte == b'O' {
-            return i - 1;
+fn draw_frame<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    frame: &Frame,
+    repetition: Repetition,
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    area.fill(&WHITE)?;
+    let (header, grid) = area.split_vertically(HEADER_SIZE);
+
+    let tilted = match frame.tilted {
+        Some(direction) => format!("tilted {:?}", direction),
+        None => "start".to_string(),
+    };
+    header.draw(&Text::new(
+        format!(
+            "cycle {} ({}), north load {}",
+            frame.cycle,
+            tilted,
+            frame.dish.north_load()
+        ),
+        (5, 5),
+        ("sans-serif", 18),
+    ))?;
+
+    // The repeated state is only reached once the east tilt finishes the cycle.
+    let completed_cycle = frame.tilted.is_none_or(|d| d == Direction::East);
+    let at_start = frame.cycle == repetition.start && completed_cycle;
+    let in_loop = frame.cycle > repetition.start || at_start;
+    let loop_text = if at_start {
+        format!("repeating cycle begins here (period {})", repetition.period)
+    } else if in_loop {
+        format!(
+            "inside the loop, {} cycles after it began",
+            frame.cycle - repetition.start
+        )
+    } else {
+        format!("loop begins after cycle {}", repetition.start)
+    };
+    let loop_color = if in_loop { RED } else { BLACK };
+    header.draw(&Text::new(
+        loop_text,
+        (5, 27),
+        ("sans-serif", 16).into_font().color(&loop_color),
+    ))?;
+
+    let rock_color = if in_loop { RED } else { BLUE };
+    let cell = CELL_SIZE as i32;
+    for y in 0..frame.dish.rocks.height() {
+        for x in 0..frame.dish.rocks.width() {
+            let style = if frame.dish.walls.get(x, y) {
+                BLACK.filled()
+            } else if frame.dish.rocks.get(x, y) {
+                rock_color.filled()
+            } else {
+                continue;
+            };
+            let (x, y) = (x as i32 * cell, y as i32 * cell);
+            grid.draw(&Rectangle::new([(x, y), (x + cell, y + cell)], style))?;
         }
     }
 
-    return len - 1;
+    area.present()?;
+    Ok(())
 }
 
-pub fn part_one(_args: Args) {
-    let mut input = input();
-    roll_north(&mut input);
-    println!(
-        "{}",
-        input
-            .iter()
-            .map(|r| String::from_utf8(r.to_owned()).unwrap())
-            .join("\n")
+/// Draws each tilt of the first `cycles` spin cycles, either as an animated GIF or as a
+/// numbered SVG file per frame next to `path`. Without `cycles`, records until the loop has
+/// come around once.
+fn render(dish: &Dish, cycles: Option<usize>, path: &Path) -> anyhow::Result<()> {
+    let repetition = find_repetition(dish);
+    let cycles = cycles.unwrap_or(repetition.start + repetition.period);
+    let frames = record_frames(dish, cycles);
+    let size = (
+        dish.rocks.width() as u32 * CELL_SIZE,
+        dish.rocks.height() as u32 * CELL_SIZE + HEADER_SIZE,
     );
-    let len = input.len();
-    let res = input
-        .iter()
-        .enumerate()
-        .map(|(i, row)| row.iter().cloned().filter(|b| b == &b'O').count() * (len - i))
-        .sum::<usize>();
-    println!("Sum: {}", res);
-}
-
-fn hash(iter: &[Vec<u8>]) -> u64 {
-    use std::hash::Hasher;
-    let mut hasher = DefaultHasher::new();
 
-    for row in iter {
-        for byte in row {
-            hasher.write_u8(*byte);
+    let written = match path.extension().and_then(|e| e.to_str()) {
+        Some("gif") => {
+            let area = BitMapBackend::gif(path, size, 250)?.into_drawing_area();
+            for frame in &frames {
+                draw_frame(&area, frame, repetition)?;
+            }
+            path.to_path_buf()
         }
-        hasher.write_u8(b'\n');
-    }
+        Some("svg") => {
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| anyhow!("Render path has no file name"))?;
+            for (i, frame) in frames.iter().enumerate() {
+                let frame_path = path.with_file_name(format!("{}_{:03}.svg", stem, i));
+                let area = SVGBackend::new(&frame_path, size).into_drawing_area();
+                draw_frame(&area, frame, repetition)?;
+            }
+            path.with_file_name(format!("{}_NNN.svg", stem))
+        }
+        _ => return Err(anyhow!("Expected a .gif or .svg render path")),
+    };
 
-    hasher.finish()
+    println!("Rendered {} frames to {}", frames.len(), written.display());
+    Ok(())
 }
 
-pub fn part_two(_args: Args) {
-    let mut input = input();
-
-    let mut hashed = HashMap::new();
+pub fn part_one(_args: Args) {
+    let mut dish: Dish = FILE_CONTENTS.parse().unwrap();
+    dish.tilt(Direction::North);
+    print!("{}", dish);
+    println!("Sum: {}", dish.north_load());
+}
 
-    for i in 0..1_000_000_000 {
-        if i % 1000 == 0 {
-            println!("{}", i);
-        }
+pub fn part_two(args: Args) {
+    let mut dish: Dish = FILE_CONTENTS.parse().unwrap();
 
-        roll_north(&mut input);
-        roll_west(&mut input);
-        roll_south(&mut input);
-        roll_east(&mut input);
-
-        let hash = hash(input.as_slice());
-        if hashed.contains_key(&hash) {
-            // We've already encountered this once, so we're in a loop
-            let prev_idx = hashed.get(&hash).unwrap();
-            let repeat_start = 1_000_000_000 - prev_idx;
-            let diff = i - prev_idx;
-            let num_iterations_left_in_repetition = (repeat_start % diff) - 1;
-            for _ in 0..num_iterations_left_in_repetition {
-                roll_north(&mut input);
-                roll_west(&mut input);
-                roll_south(&mut input);
-                roll_east(&mut input);
-            }
-            break;
-        }
-        hashed.insert(hash, i);
+    if let Some(path) = &args.render {
+        render(&dish, args.cycles, path).unwrap();
     }
+
+    let repetition = find_repetition(&dish);
     println!(
-        "{}",
-        input
-            .iter()
-            .map(|r| String::from_utf8(r.to_owned()).unwrap())
-            .join("\n")
+        "Repeats every {} cycles from cycle {}",
+        repetition.period, repetition.start
     );
-    let len = input.len();
-    let res = input
-        .iter()
-        .enumerate()
-        .map(|(i, row)| row.iter().cloned().filter(|b| b == &b'O').count() * (len - i))
-        .sum::<usize>();
-    println!("Sum: {}", res);
+    let target = 1_000_000_000;
+    let cycles = if target < repetition.start {
+        target
+    } else {
+        repetition.start + (target - repetition.start) % repetition.period
+    };
+    for _ in 0..cycles {
+        dish.spin_cycle();
+    }
+    print!("{}", dish);
+    println!("Sum: {}", dish.north_load());
 }
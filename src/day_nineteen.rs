@@ -1,16 +1,22 @@
-use std::{collections::HashMap, iter::Sum, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    iter::Sum,
+    ops::{Index, IndexMut, Range},
+    str::FromStr,
+};
 
-use itertools::{iproduct, Itertools};
+use itertools::Itertools;
 
-use crate::Args;
+use crate::{render::write_dot, Args};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_nineteen.txt");
 
 type Destination = String;
 #[derive(Debug, Clone)]
 enum Logic {
-    GreaterThan(char, u32, Command),
-    LessThan(char, u32, Command),
+    GreaterThan(Category, u32, Command),
+    LessThan(Category, u32, Command),
     Command(Command),
 }
 
@@ -21,11 +27,47 @@ enum Command {
     Redirect(Destination),
 }
 
+/// One of the four ratings every part has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Category {
+    X,
+    M,
+    A,
+    S,
+}
+
+impl FromStr for Category {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x" => Ok(Category::X),
+            "m" => Ok(Category::M),
+            "a" => Ok(Category::A),
+            "s" => Ok(Category::S),
+            _ => Err(anyhow!("Unknown rating category {:?}", s)),
+        }
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Category::X => "x",
+            Category::M => "m",
+            Category::A => "a",
+            Category::S => "s",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl FromStr for Command {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "" => Err(anyhow!("Rule has no destination")),
             "A" => Ok(Command::Accept),
             "R" => Ok(Command::Reject),
             _ => Ok(Command::Redirect(s.to_owned())),
@@ -37,26 +79,23 @@ impl FromStr for Logic {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some((left, dest)) = s.split(':').collect_tuple() {
-            let is_greater_than = left.contains('>');
-
-            if is_greater_than {
-                let (part, num) = left.split('>').collect_tuple().unwrap();
-                return Ok(Self::GreaterThan(
-                    part.chars().next().unwrap(),
-                    num.parse().unwrap(),
-                    dest.parse().unwrap(),
-                ));
-            }
+        let Some((left, dest)) = s.split_once(':') else {
+            return Ok(Self::Command(s.parse()?));
+        };
 
-            let (part, num) = left.split('<').collect_tuple().unwrap();
-            return Ok(Self::LessThan(
-                part.chars().next().unwrap(),
-                num.parse().unwrap(),
-                dest.parse().unwrap(),
-            ));
-        }
-        Ok(Self::Command(s.parse().unwrap()))
+        let (split, condition): (char, fn(Category, u32, Command) -> Self) = if left.contains('>') {
+            ('>', Self::GreaterThan)
+        } else {
+            ('<', Self::LessThan)
+        };
+        let (category, num) = left
+            .split_once(split)
+            .ok_or_else(|| anyhow!("Rule {:?} has no comparison", s))?;
+        Ok(condition(
+            category.parse()?,
+            num.parse().map_err(|e| anyhow!("Rule {:?}: {}", s, e))?,
+            dest.parse()?,
+        ))
     }
 }
 
@@ -68,6 +107,30 @@ struct PartRating {
     pub s: u32,
 }
 
+impl Index<Category> for PartRating {
+    type Output = u32;
+
+    fn index(&self, category: Category) -> &Self::Output {
+        match category {
+            Category::X => &self.x,
+            Category::M => &self.m,
+            Category::A => &self.a,
+            Category::S => &self.s,
+        }
+    }
+}
+
+impl IndexMut<Category> for PartRating {
+    fn index_mut(&mut self, category: Category) -> &mut Self::Output {
+        match category {
+            Category::X => &mut self.x,
+            Category::M => &mut self.m,
+            Category::A => &mut self.a,
+            Category::S => &mut self.s,
+        }
+    }
+}
+
 impl Sum<PartRating> for u64 {
     fn sum<I: Iterator<Item = PartRating>>(iter: I) -> Self {
         iter.fold(0, |acc, p| acc + (p.x + p.m + p.a + p.s) as u64)
@@ -82,118 +145,304 @@ impl FromStr for PartRating {
             ..Default::default()
         };
 
-        for (left, right) in s
-            .replace("{", "")
-            .replace("}", "")
-            .split(",")
-            .map(|s| s.split("=").collect_tuple::<(_, _)>().unwrap())
-        {
-            match left {
-                "x" => this.x = right.parse().unwrap(),
-                "m" => this.m = right.parse().unwrap(),
-                "a" => this.a = right.parse().unwrap(),
-                "s" => this.s = right.parse().unwrap(),
-                _ => unreachable!(),
-            }
+        let ratings = s
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .ok_or_else(|| anyhow!("Part {:?} is not wrapped in braces", s))?;
+        for rating in ratings.split(',') {
+            let (category, value) = rating
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Rating {:?} has no value", rating))?;
+            this[category.parse()?] = value.parse()?;
         }
 
         Ok(this)
     }
 }
 
-fn input() -> (HashMap<Destination, Box<[Logic]>>, Vec<PartRating>) {
-    let (rules, parts) = FILE_CONTENTS.split("\n\n").collect_tuple().unwrap();
-    let rules = rules
-        .lines()
-        .map(|l| {
-            let (name, rules) = l.split("{").collect_tuple().unwrap();
-            let rules = rules.replace("}", "");
-            let rules = rules
-                .split(",")
-                .map(|r| r.parse::<Logic>().unwrap())
-                .collect();
-            (name.to_owned(), rules)
-        })
-        .collect::<HashMap<_, _>>();
+/// Index of a workflow in `RuleGraph::workflows`.
+type WorkflowId = usize;
 
-    let parts = parts
-        .lines()
-        .map(|l| l.parse::<PartRating>().unwrap())
-        .collect();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Accept,
+    Reject,
+    Workflow(WorkflowId),
+}
 
-    (rules, parts)
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    GreaterThan(Category, u32, Target),
+    LessThan(Category, u32, Target),
+    Always(Target),
 }
 
-fn handle_rule(part: &PartRating, rules: &[Logic]) -> Command {
-    for rule in rules {
-        match rule {
-            Logic::GreaterThan(category, num, command) => match category {
-                'x' => {
-                    if part.x > *num {
-                        return command.to_owned();
-                    }
-                }
-                'm' => {
-                    if part.m > *num {
-                        return command.to_owned();
-                    }
-                }
-                'a' => {
-                    if part.a > *num {
-                        return command.to_owned();
-                    }
-                }
-                's' => {
-                    if part.s > *num {
-                        return command.to_owned();
-                    }
-                }
-                _ => unreachable!(),
-            },
-            Logic::LessThan(category, num, command) => match category {
-                'x' => {
-                    if part.x < *num {
-                        return command.to_owned();
-                    }
-                }
-                'm' => {
-                    if part.m < *num {
-                        return command.to_owned();
+impl Rule {
+    /// Where a part goes if this rule matches it, or `None` to fall through to the next rule.
+    pub fn apply(&self, part: &PartRating) -> Option<Target> {
+        match *self {
+            Rule::GreaterThan(category, num, target) => (part[category] > num).then_some(target),
+            Rule::LessThan(category, num, target) => (part[category] < num).then_some(target),
+            Rule::Always(target) => Some(target),
+        }
+    }
+}
+
+/// The workflows with every destination resolved to an index, checked so that every part
+/// ends up accepted or rejected.
+#[derive(Debug, Clone)]
+struct RuleGraph {
+    names: Vec<String>,
+    workflows: Vec<Box<[Rule]>>,
+    start: WorkflowId,
+}
+
+impl RuleGraph {
+    pub fn compile(parsed: &[(String, Box<[Logic]>)]) -> anyhow::Result<Self> {
+        let mut ids = HashMap::new();
+        for (id, (name, _)) in parsed.iter().enumerate() {
+            if ids.insert(name.as_str(), id).is_some() {
+                return Err(anyhow!("Workflow {} is defined more than once", name));
+            }
+        }
+
+        let resolve = |from: &str, command: &Command| match command {
+            Command::Accept => Ok(Target::Accept),
+            Command::Reject => Ok(Target::Reject),
+            Command::Redirect(name) => ids
+                .get(name.as_str())
+                .map(|id| Target::Workflow(*id))
+                .ok_or_else(|| {
+                    anyhow!("Workflow {} sends parts to unknown workflow {}", from, name)
+                }),
+        };
+
+        let mut workflows = Vec::with_capacity(parsed.len());
+        for (name, logic) in parsed {
+            if !matches!(logic.last(), Some(Logic::Command(_))) {
+                return Err(anyhow!("Workflow {} has no fallback rule", name));
+            }
+            let rules = logic
+                .iter()
+                .map(|l| {
+                    Ok(match l {
+                        Logic::GreaterThan(category, num, command) => {
+                            Rule::GreaterThan(*category, *num, resolve(name, command)?)
+                        }
+                        Logic::LessThan(category, num, command) => {
+                            Rule::LessThan(*category, *num, resolve(name, command)?)
+                        }
+                        Logic::Command(command) => Rule::Always(resolve(name, command)?),
+                    })
+                })
+                .collect::<anyhow::Result<Box<[Rule]>>>()?;
+            workflows.push(rules);
+        }
+
+        let graph = Self {
+            names: parsed.iter().map(|(name, _)| name.clone()).collect(),
+            workflows,
+            start: *ids
+                .get("in")
+                .ok_or_else(|| anyhow!("There is no starting workflow named in"))?,
+        };
+        graph.check_for_cycles()?;
+        Ok(graph)
+    }
+
+    fn successors(&self, id: WorkflowId) -> impl Iterator<Item = WorkflowId> + '_ {
+        self.workflows[id].iter().filter_map(|rule| match rule {
+            Rule::GreaterThan(_, _, Target::Workflow(next))
+            | Rule::LessThan(_, _, Target::Workflow(next))
+            | Rule::Always(Target::Workflow(next)) => Some(*next),
+            _ => None,
+        })
+    }
+
+    /// A part that revisits a workflow would never be accepted or rejected.
+    fn check_for_cycles(&self) -> anyhow::Result<()> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Unvisited,
+            InProgress,
+            Done,
+        }
+
+        let mut marks = vec![Mark::Unvisited; self.workflows.len()];
+        for root in 0..self.workflows.len() {
+            if marks[root] != Mark::Unvisited {
+                continue;
+            }
+            let mut stack = vec![(root, self.successors(root).collect_vec())];
+            marks[root] = Mark::InProgress;
+            while let Some((id, successors)) = stack.last_mut() {
+                let id = *id;
+                match successors.pop() {
+                    Some(next) if marks[next] == Mark::InProgress => {
+                        let cycle = stack
+                            .iter()
+                            .map(|(id, _)| *id)
+                            .skip_while(|id| *id != next)
+                            .chain(std::iter::once(next))
+                            .map(|id| self.names[id].as_str())
+                            .join(" -> ");
+                        return Err(anyhow!("Workflows form a cycle: {}", cycle));
                     }
-                }
-                'a' => {
-                    if part.a < *num {
-                        return command.to_owned();
+                    Some(next) if marks[next] == Mark::Unvisited => {
+                        marks[next] = Mark::InProgress;
+                        stack.push((next, self.successors(next).collect_vec()));
                     }
-                }
-                's' => {
-                    if part.s < *num {
-                        return command.to_owned();
+                    Some(_) => {}
+                    None => {
+                        marks[id] = Mark::Done;
+                        stack.pop();
                     }
                 }
-                _ => unreachable!(),
-            },
-            Logic::Command(comm) => return comm.to_owned(),
+            }
         }
+        Ok(())
     }
 
-    unreachable!()
-}
+    /// Runs a part through the workflows, returning where it ended up.
+    pub fn evaluate(&self, part: &PartRating) -> Target {
+        self.route(part).1
+    }
 
-fn should_be_accepted(part: &PartRating, rules: &HashMap<String, Box<[Logic]>>) -> bool {
-    let mut rule = rules.get("in").unwrap().as_ref();
+    /// The workflows a part passes through, each with the index of the rule that matched it,
+    /// and where it ended up.
+    pub fn route(&self, part: &PartRating) -> (Vec<(WorkflowId, usize)>, Target) {
+        let mut route = Vec::new();
+        let mut id = self.start;
+        loop {
+            let (index, target) = self.workflows[id]
+                .iter()
+                .enumerate()
+                .find_map(|(index, rule)| Some((index, rule.apply(part)?)))
+                .expect("Every workflow ends with a fallback rule");
+            route.push((id, index));
+            match target {
+                Target::Workflow(next) => id = next,
+                target => return (route, target),
+            }
+        }
+    }
 
-    loop {
-        match handle_rule(part, rule) {
-            Command::Accept => return true,
-            Command::Reject => return false,
-            Command::Redirect(r) => rule = rules.get(r.as_str()).unwrap().as_ref(),
+    /// A Graphviz diagram with one node per workflow and one edge per rule, labelled with the
+    /// rule's condition. Edges along `route` are drawn in red.
+    pub fn to_dot(&self, route: &[(WorkflowId, usize)]) -> String {
+        let node = |target: Target| match target {
+            Target::Accept => "A".to_owned(),
+            Target::Reject => "R".to_owned(),
+            Target::Workflow(id) => format!("w{}", id),
+        };
+        let highlight = ", color=red, fontcolor=red, penwidth=2";
+
+        let mut dot = String::from("digraph workflows {\n    rankdir=LR;\n");
+        dot += "    A [label=\"A\", shape=doublecircle, style=filled, fillcolor=palegreen];\n";
+        dot += "    R [label=\"R\", shape=doublecircle, style=filled, fillcolor=lightpink];\n";
+        for (id, name) in self.names.iter().enumerate() {
+            let style = if route.iter().any(|(visited, _)| *visited == id) {
+                highlight
+            } else {
+                ""
+            };
+            dot += &format!("    w{} [label=\"{}\", shape=box{}];\n", id, name, style);
+        }
+        for (id, rules) in self.workflows.iter().enumerate() {
+            for (index, rule) in rules.iter().enumerate() {
+                let label = match rule {
+                    Rule::GreaterThan(category, num, _) => format!("{}>{}", category, num),
+                    Rule::LessThan(category, num, _) => format!("{}<{}", category, num),
+                    Rule::Always(_) => "else".to_owned(),
+                };
+                let style = if route.contains(&(id, index)) {
+                    highlight
+                } else {
+                    ""
+                };
+                dot += &format!(
+                    "    w{} -> {} [label=\"{}\"{}];\n",
+                    id,
+                    node(rule.target()),
+                    label,
+                    style
+                );
+            }
         }
+        dot += "}\n";
+        dot
     }
 }
 
-pub fn part_one(_args: Args) {
-    let (rules, parts) = input();
+/// Writes the workflows to the `--render` path as DOT, following the `--rating` part if given.
+fn export_dot(args: &Args, rules: &RuleGraph) -> anyhow::Result<()> {
+    let Some(path) = &args.render else {
+        return Ok(());
+    };
+    let route = match &args.rating {
+        Some(rating) => {
+            let part = rating.parse::<PartRating>()?;
+            let (route, target) = rules.route(&part);
+            println!(
+                "{} is {} via {}",
+                rating,
+                if target == Target::Accept {
+                    "accepted"
+                } else {
+                    "rejected"
+                },
+                route
+                    .iter()
+                    .map(|(id, _)| rules.names[*id].as_str())
+                    .join(" -> ")
+            );
+            route
+        }
+        None => Vec::new(),
+    };
+
+    write_dot(path, &rules.to_dot(&route))
+}
+
+/// Workflows by name, in the order they appear in the input.
+type Workflows = Vec<(Destination, Box<[Logic]>)>;
+
+fn input() -> anyhow::Result<(Workflows, Vec<PartRating>)> {
+    let (rules, parts) = FILE_CONTENTS
+        .split("\n\n")
+        .collect_tuple()
+        .ok_or_else(|| anyhow!("Expected workflows and parts separated by a blank line"))?;
+    let rules = rules
+        .lines()
+        .map(|l| {
+            let (name, rules) = l
+                .strip_suffix('}')
+                .and_then(|l| l.split_once('{'))
+                .ok_or_else(|| anyhow!("Workflow {:?} is malformed", l))?;
+            let rules = rules
+                .split(',')
+                .map(|r| r.parse::<Logic>())
+                .collect::<anyhow::Result<_>>()?;
+            Ok((name.to_owned(), rules))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let parts = parts
+        .lines()
+        .map(|l| l.parse::<PartRating>())
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok((rules, parts))
+}
+
+fn should_be_accepted(part: &PartRating, rules: &RuleGraph) -> bool {
+    rules.evaluate(part) == Target::Accept
+}
+
+pub fn part_one(args: Args) {
+    let (rules, parts) = input().unwrap();
+    let rules = RuleGraph::compile(&rules).unwrap();
+    export_dot(&args, &rules).unwrap();
 
     let sum = parts
         .into_iter()
@@ -203,258 +452,180 @@ pub fn part_one(_args: Args) {
     println!("Sum: {}", sum);
 }
 
-#[derive(Debug, Clone)]
-struct PartRange {
-    pub x: std::ops::Range<u32>,
-    pub m: std::ops::Range<u32>,
-    pub a: std::ops::Range<u32>,
-    pub s: std::ops::Range<u32>,
+/// The half-open range of values each category can take, i.e. a box in rating space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PartBox {
+    ranges: [Range<u32>; 4],
 }
 
-pub fn part_two(_args: Args) {
-    let (rule_map, _) = input();
+impl Index<Category> for PartBox {
+    type Output = Range<u32>;
 
-    let starting_range = PartRange {
-        x: 1..4001,
-        m: 1..4001,
-        a: 1..4001,
-        s: 1..4001,
-    };
+    fn index(&self, category: Category) -> &Self::Output {
+        &self.ranges[category as usize]
+    }
+}
 
-    let mut rules_to_process = vec![(starting_range, rule_map.get("in").unwrap().as_ref())];
-    let mut accepted: Vec<PartRange> = Vec::new();
-    while let Some((mut part_range, rules)) = rules_to_process.pop() {
-        for rule in rules {
-            match rule {
-                Logic::GreaterThan(c, num, command) => match c {
-                    'x' => {
-                        if !part_range.x.contains(num) {
-                            continue;
-                        }
+impl IndexMut<Category> for PartBox {
+    fn index_mut(&mut self, category: Category) -> &mut Self::Output {
+        &mut self.ranges[category as usize]
+    }
+}
 
-                        let min = part_range.x.start;
-                        let max = part_range.x.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.x = num + 1..max;
-                        part_range.x = min..*num + 1;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    'm' => {
-                        if !part_range.m.contains(num) {
-                            continue;
-                        }
+impl Display for PartBox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [x, m, a, s] = &self.ranges;
+        write!(
+            f,
+            "x={}..={} m={}..={} a={}..={} s={}..={}",
+            x.start,
+            x.end - 1,
+            m.start,
+            m.end - 1,
+            a.start,
+            a.end - 1,
+            s.start,
+            s.end - 1
+        )
+    }
+}
 
-                        let min = part_range.m.start;
-                        let max = part_range.m.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.m = num + 1..max;
-                        part_range.m = min..*num + 1;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    'a' => {
-                        if !part_range.a.contains(num) {
-                            continue;
-                        }
+impl PartBox {
+    /// Every category ranging over `min..=max`.
+    pub fn uniform(min: u32, max: u32) -> anyhow::Result<Self> {
+        if min > max {
+            return Err(anyhow!(
+                "The minimum rating {} is greater than the maximum {}",
+                min,
+                max
+            ));
+        }
+        let end = max
+            .checked_add(1)
+            .ok_or_else(|| anyhow!("The maximum rating must be below {}", u32::MAX))?;
+        Ok(Self {
+            ranges: std::array::from_fn(|_| min..end),
+        })
+    }
 
-                        let min = part_range.a.start;
-                        let max = part_range.a.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.a = num + 1..max;
-                        part_range.a = min..*num + 1;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    's' => {
-                        if !part_range.s.contains(num) {
-                            continue;
-                        }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.iter().any(|r| r.is_empty())
+    }
 
-                        let min = part_range.s.start;
-                        let max = part_range.s.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.s = num + 1..max;
-                        part_range.s = min..*num + 1;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    _ => unreachable!(),
-                },
-                Logic::LessThan(c, num, command) => match c {
-                    'x' => {
-                        if !part_range.x.contains(num) {
-                            continue;
-                        }
+    /// The number of distinct parts inside the box.
+    pub fn volume(&self) -> u128 {
+        self.ranges
+            .iter()
+            .map(|r| r.end.saturating_sub(r.start) as u128)
+            .product()
+    }
 
-                        let min = part_range.x.start;
-                        let max = part_range.x.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.x = min..*num;
-                        part_range.x = *num..max;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    'm' => {
-                        if !part_range.m.contains(num) {
-                            continue;
-                        }
+    /// Cuts the box in two along `category`, the first half holding values below `at`.
+    fn split(&self, category: Category, at: u32) -> (Self, Self) {
+        let range = &self[category];
+        let at = at.clamp(range.start, range.end);
+        let (mut below, mut above) = (self.clone(), self.clone());
+        below[category] = range.start..at;
+        above[category] = at..range.end;
+        (below, above)
+    }
+}
 
-                        let min = part_range.m.start;
-                        let max = part_range.m.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.m = min..*num;
-                        part_range.m = *num..max;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    'a' => {
-                        if !part_range.a.contains(num) {
-                            continue;
-                        }
+impl Rule {
+    pub fn target(&self) -> Target {
+        match *self {
+            Rule::GreaterThan(_, _, target)
+            | Rule::LessThan(_, _, target)
+            | Rule::Always(target) => target,
+        }
+    }
 
-                        let min = part_range.a.start;
-                        let max = part_range.a.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.a = min..*num;
-                        part_range.a = *num..max;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    's' => {
-                        if !part_range.s.contains(num) {
-                            continue;
-                        }
+    /// Splits `part_box` into the parts this rule sends to its target and the parts that
+    /// fall through to the next rule, if any can.
+    pub fn partition(&self, part_box: &PartBox) -> (PartBox, Option<PartBox>) {
+        match *self {
+            Rule::GreaterThan(category, num, _) => {
+                let (rest, matched) = part_box.split(category, num.saturating_add(1));
+                (matched, Some(rest))
+            }
+            Rule::LessThan(category, num, _) => {
+                let (matched, rest) = part_box.split(category, num);
+                (matched, Some(rest))
+            }
+            Rule::Always(_) => (part_box.clone(), None),
+        }
+    }
+}
 
-                        let min = part_range.s.start;
-                        let max = part_range.s.end;
-
-                        let mut part_clone = part_range.clone();
-                        part_clone.s = min..*num;
-                        part_range.s = *num..max;
-
-                        let rule = match command {
-                            Command::Accept => {
-                                accepted.push(part_clone);
-                                continue;
-                            }
-                            Command::Reject => {
-                                continue;
-                            }
-                            Command::Redirect(r) => rule_map.get(r.as_str()).unwrap().as_ref(),
-                        };
-
-                        rules_to_process.push((part_clone, rule));
-                    }
-                    _ => unreachable!(),
-                },
-                Logic::Command(c) => match c {
-                    Command::Accept => {
-                        accepted.push(part_range.clone());
-                        continue;
-                    }
-                    Command::Reject => {
-                        continue;
+/// A box of parts that are all accepted, along with the workflows they pass through.
+#[derive(Debug, Clone)]
+struct AcceptedRegion {
+    part_box: PartBox,
+    path: Vec<WorkflowId>,
+}
+
+impl RuleGraph {
+    /// Pushes `bounds` through the workflows symbolically, returning disjoint boxes that
+    /// together hold every accepted part.
+    pub fn accepted_regions(&self, bounds: PartBox) -> Vec<AcceptedRegion> {
+        let mut accepted = Vec::new();
+        let mut pending = vec![(bounds, vec![self.start])];
+        while let Some((part_box, path)) = pending.pop() {
+            let id = *path.last().unwrap();
+            let mut remaining = Some(part_box);
+            for rule in self.workflows[id].iter() {
+                let Some(part_box) = remaining.take().filter(|b| !b.is_empty()) else {
+                    break;
+                };
+                let (matched, rest) = rule.partition(&part_box);
+                remaining = rest;
+                if matched.is_empty() {
+                    continue;
+                }
+                match rule.target() {
+                    Target::Accept => accepted.push(AcceptedRegion {
+                        part_box: matched,
+                        path: path.clone(),
+                    }),
+                    Target::Reject => {}
+                    Target::Workflow(next) => {
+                        let mut path = path.clone();
+                        path.push(next);
+                        pending.push((matched, path));
                     }
-                    Command::Redirect(r) => rules_to_process.push((
-                        part_range.clone(),
-                        rule_map.get(r.as_str()).unwrap().as_ref(),
-                    )),
-                },
+                }
             }
         }
+        accepted
     }
+}
 
-    println!("{:#?}", accepted);
-
-    let mut count = 0;
-    for part_range in accepted {
-        count += iproduct!(part_range.x, part_range.m, part_range.a, part_range.s).count();
+pub fn part_two(args: Args) {
+    let (rules, _) = input().unwrap();
+    let rules = RuleGraph::compile(&rules).unwrap();
+    export_dot(&args, &rules).unwrap();
+
+    let bounds = PartBox::uniform(args.min_rating, args.max_rating).unwrap();
+    let regions = rules.accepted_regions(bounds);
+
+    if args.trace {
+        for region in regions.iter().take(args.limit) {
+            println!(
+                "{} ({} parts) via {}",
+                region.part_box,
+                region.part_box.volume(),
+                region
+                    .path
+                    .iter()
+                    .map(|id| rules.names[*id].as_str())
+                    .join(" -> ")
+            );
+        }
+        if regions.len() > args.limit {
+            println!("... and {} more", regions.len() - args.limit);
+        }
     }
 
+    let count = regions.iter().map(|r| r.part_box.volume()).sum::<u128>();
     println!("Count: {}", count);
 }
@@ -1,258 +1,219 @@
-use std::{collections::BTreeMap, ops, str::FromStr};
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+};
 
 use crate::Args;
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_one.txt");
 
-pub fn part_one(_args: Args) {
-    let sum = FILE_CONTENTS
-        .split('\n')
-        .filter_map(|c| {
-            let mut chars = c.chars().filter(|c: &char| c.is_numeric());
-            let next_digit = chars.next().unwrap();
-            let data = if let Some(last_digit) = chars.rev().next() {
-                [next_digit, last_digit]
-            } else {
-                [next_digit, next_digit]
-            };
-            return Some(data.iter().collect::<String>().parse::<i64>().unwrap());
-        })
-        .reduce(|acc, x| acc + x)
-        .unwrap();
-    println!("{:?}", sum);
-}
-
-struct NumberLike {
-    pub inner: u64,
-}
+const ENGLISH: &str = "one=1,two=2,three=3,four=4,five=5,six=6,seven=7,eight=8,nine=9";
 
-lazy_static::lazy_static! {
-    static ref TREE: BTreeMap<char, TreeEntry> = build_tree();
+/// Spelled out digits, parsed from comma separated `word=value` pairs with values 0 to 9.
+#[derive(Debug, Clone, Default)]
+struct Vocabulary {
+    words: Vec<(String, u64)>,
 }
 
-impl FromStr for NumberLike {
-    type Err = std::fmt::Error;
+impl FromStr for Vocabulary {
+    type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        println!("---------------");
-        println!("Starting {}", s);
-        let _chars = s.chars();
-
-        let mut node: &BTreeMap<_, _> = &TREE;
-        let mut first = None;
-        let mut last = None;
-        let buf = s.chars().collect::<Vec<_>>();
-        let mut next = 0;
-        let len = buf.len();
-
-        while next < len as u64 {
-            let c = buf[next as usize];
-
-            if c.is_numeric() {
-                println!("{} is numeric", c);
-                if first.is_none() {
-                    first = Some(c.to_digit(10).unwrap() as u64);
-                } else {
-                    last = Some(c.to_digit(10).unwrap() as u64);
+        let words = s
+            .split(',')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (word, value) = pair
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Expected word=value, not {:?}", pair))?;
+                if word.is_empty() {
+                    return Err(anyhow!("Empty word in {:?}", pair));
                 }
-                node = &TREE;
-                next += 1;
-                continue;
-            }
-
-            if !TREE.contains_key(&c) {
-                println!("Invalid character {}", c);
-                next += 1;
-                continue;
-            }
-
-            let entry = node.get(&c).unwrap();
-            println!("Found entry: {}", c);
+                let value = value.parse()?;
+                if value > 9 {
+                    return Err(anyhow!("{} is not a single digit in {:?}", value, pair));
+                }
+                Ok((word.to_owned(), value))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { words })
+    }
+}
 
-            match entry {
-                TreeEntry::InnerTree(btree) => {
-                    println!("Entry was tree");
-                    node = btree;
+/// A match of a word, with `start..end` counted in chars from the start of the scan.
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    start: usize,
+    end: usize,
+    value: u64,
+}
 
-                    let mut i = 0;
-                    loop {
-                        i += 1;
-                        if (next as usize + i) >= len {
-                            break;
-                        }
-                        let c = buf[(next as usize + i) as usize];
-                        if !node.contains_key(&c) {
-                            break;
-                        }
+/// An Aho–Corasick automaton that finds every, possibly overlapping, occurrence of a set of
+/// words in a single pass.
+#[derive(Debug, Clone)]
+struct Matcher {
+    /// Trie edges out of each node, the root being node 0.
+    children: Vec<HashMap<char, usize>>,
+    /// The node for the longest proper suffix of each node's text that is also in the trie.
+    fail: Vec<usize>,
+    /// Words ending at each node, directly or through `fail`, as (length, value).
+    outputs: Vec<Vec<(usize, u64)>>,
+    longest: usize,
+}
 
-                        let next_node = node.get(&c).unwrap();
-                        match next_node {
-                            TreeEntry::InnerTree(btree) => {
-                                println!("Entry was tree");
-                                node = btree;
-                            }
-                            TreeEntry::Number(num) => {
-                                println!("Entry was number");
-                                if first.is_none() {
-                                    first = Some(*num);
-                                } else {
-                                    last = Some(*num);
-                                }
-                                break;
-                            }
-                        }
-                    }
-                }
-                TreeEntry::Number(num) => {
-                    println!("Entry was number");
-                    if first.is_none() {
-                        first = Some(*num);
-                    } else {
-                        last = Some(*num);
+impl Matcher {
+    pub fn new<W: IntoIterator<Item = char>>(words: impl IntoIterator<Item = (W, u64)>) -> Self {
+        let mut matcher = Self {
+            children: vec![HashMap::new()],
+            fail: vec![0],
+            outputs: vec![Vec::new()],
+            longest: 0,
+        };
+
+        for (word, value) in words {
+            let mut node = 0;
+            let mut len = 0;
+            for c in word {
+                node = match matcher.children[node].get(&c) {
+                    Some(next) => *next,
+                    None => {
+                        matcher.children.push(HashMap::new());
+                        matcher.fail.push(0);
+                        matcher.outputs.push(Vec::new());
+                        let next = matcher.children.len() - 1;
+                        matcher.children[node].insert(c, next);
+                        next
                     }
-                }
+                };
+                len += 1;
             }
+            matcher.outputs[node].push((len, value));
+            matcher.longest = matcher.longest.max(len);
+        }
 
-            next += 1;
+        // Breadth first, so every node's suffix has its links before the node itself
+        let mut queue = matcher.children[0]
+            .values()
+            .copied()
+            .collect::<VecDeque<_>>();
+        while let Some(node) = queue.pop_front() {
+            let children = matcher.children[node]
+                .iter()
+                .map(|(c, child)| (*c, *child))
+                .collect::<Vec<_>>();
+            for (c, child) in children {
+                let fail = matcher.step(matcher.fail[node], c);
+                matcher.fail[child] = fail;
+                let inherited = matcher.outputs[fail].clone();
+                matcher.outputs[child].extend(inherited);
+                queue.push_back(child);
+            }
         }
 
-        if last.is_none() {
-            last = first;
+        matcher
+    }
+
+    fn step(&self, mut node: usize, c: char) -> usize {
+        loop {
+            if let Some(next) = self.children[node].get(&c) {
+                return *next;
+            }
+            if node == 0 {
+                return 0;
+            }
+            node = self.fail[node];
         }
+    }
 
-        println!("Finishing: {}{}", first.unwrap(), last.unwrap());
-        println!("---------------");
+    /// Every match in `chars`, ordered by where they end.
+    pub fn matches<'a>(
+        &'a self,
+        chars: impl Iterator<Item = char> + 'a,
+    ) -> impl Iterator<Item = Match> + 'a {
+        chars
+            .enumerate()
+            .scan(0, |node, (i, c)| {
+                *node = self.step(*node, c);
+                Some((i + 1, *node))
+            })
+            .flat_map(|(end, node)| {
+                self.outputs[node].iter().map(move |(len, value)| Match {
+                    start: end - len,
+                    end,
+                    value: *value,
+                })
+            })
+    }
 
-        return Ok(Self {
-            inner: (first.unwrap() * 10) + last.unwrap(),
-        });
+    /// The match that starts first, stopping the scan once no later match could start earlier.
+    pub fn first(&self, chars: impl Iterator<Item = char>) -> Option<Match> {
+        let mut best: Option<Match> = None;
+        for found in self.matches(chars) {
+            if best.is_some_and(|b| found.end >= b.start + self.longest) {
+                break;
+            }
+            if best.is_none_or(|b| found.start < b.start) {
+                best = Some(found);
+            }
+        }
+        best
     }
 }
 
-impl ops::Add for NumberLike {
-    type Output = NumberLike;
+/// Finds the first and last digit on a line, whether written as a digit or a word.
+#[derive(Debug, Clone)]
+struct DigitScanner {
+    forward: Matcher,
+    /// Matches the words reversed, for scanning lines from the end.
+    backward: Matcher,
+}
 
-    fn add(self, rhs: Self) -> Self::Output {
+impl DigitScanner {
+    pub fn new(vocabulary: &Vocabulary) -> Self {
+        let words = (0..10)
+            .map(|d| (d.to_string(), d))
+            .chain(vocabulary.words.iter().cloned())
+            .collect::<Vec<_>>();
         Self {
-            inner: self.inner + rhs.inner,
+            forward: Matcher::new(words.iter().map(|(w, v)| (w.chars(), *v))),
+            backward: Matcher::new(words.iter().map(|(w, v)| (w.chars().rev(), *v))),
         }
     }
-}
 
-impl std::fmt::Display for NumberLike {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.inner)
+    pub fn calibration_value(&self, line: &str) -> anyhow::Result<u64> {
+        let first = self
+            .forward
+            .first(line.chars())
+            .ok_or_else(|| anyhow!("{:?} has no digits", line))?;
+        let last = self
+            .backward
+            .first(line.chars().rev())
+            .ok_or_else(|| anyhow!("{:?} has no digits", line))?;
+        Ok(first.value * 10 + last.value)
     }
 }
 
-pub fn part_two(_args: Args) {
-    let sum = FILE_CONTENTS
-        .split('\n')
-        .filter_map(|c| {
-            let res = c.parse::<NumberLike>().unwrap();
-            println!("{}", res);
-            return Some(res);
-        })
-        .reduce(|acc, x| acc + x)
-        .unwrap();
-    println!("{:?}", sum.inner);
+fn sum_calibration_values(scanner: &DigitScanner) -> anyhow::Result<u64> {
+    FILE_CONTENTS
+        .lines()
+        .map(|line| scanner.calibration_value(line))
+        .sum()
 }
 
-enum TreeEntry {
-    Number(u64),
-    InnerTree(BTreeMap<char, TreeEntry>),
+pub fn part_one(_args: Args) {
+    let scanner = DigitScanner::new(&Vocabulary::default());
+    let sum = sum_calibration_values(&scanner).unwrap();
+    println!("{:?}", sum);
 }
 
-fn build_tree() -> BTreeMap<char, TreeEntry> {
-    let tree = BTreeMap::from([
-        (
-            'o',
-            TreeEntry::InnerTree(BTreeMap::from([(
-                'n',
-                TreeEntry::InnerTree(BTreeMap::from([('e', TreeEntry::Number(1))])),
-            )])),
-        ),
-        (
-            't',
-            TreeEntry::InnerTree(BTreeMap::from([
-                (
-                    'w',
-                    TreeEntry::InnerTree(BTreeMap::from([('o', TreeEntry::Number(2))])),
-                ),
-                (
-                    'h',
-                    TreeEntry::InnerTree(BTreeMap::from([(
-                        'r',
-                        TreeEntry::InnerTree(BTreeMap::from([(
-                            'e',
-                            TreeEntry::InnerTree(BTreeMap::from([('e', TreeEntry::Number(3))])),
-                        )])),
-                    )])),
-                ),
-            ])),
-        ),
-        (
-            'f',
-            TreeEntry::InnerTree(BTreeMap::from([
-                (
-                    'o',
-                    TreeEntry::InnerTree(BTreeMap::from([(
-                        'u',
-                        TreeEntry::InnerTree(BTreeMap::from([('r', TreeEntry::Number(4))])),
-                    )])),
-                ),
-                (
-                    'i',
-                    TreeEntry::InnerTree(BTreeMap::from([(
-                        'v',
-                        TreeEntry::InnerTree(BTreeMap::from([('e', TreeEntry::Number(5))])),
-                    )])),
-                ),
-            ])),
-        ),
-        (
-            's',
-            TreeEntry::InnerTree(BTreeMap::from([
-                (
-                    'i',
-                    TreeEntry::InnerTree(BTreeMap::from([('x', TreeEntry::Number(6))])),
-                ),
-                (
-                    'e',
-                    TreeEntry::InnerTree(BTreeMap::from([(
-                        'v',
-                        TreeEntry::InnerTree(BTreeMap::from([(
-                            'e',
-                            TreeEntry::InnerTree(BTreeMap::from([('n', TreeEntry::Number(7))])),
-                        )])),
-                    )])),
-                ),
-            ])),
-        ),
-        (
-            'e',
-            TreeEntry::InnerTree(BTreeMap::from([(
-                'i',
-                TreeEntry::InnerTree(BTreeMap::from([(
-                    'g',
-                    TreeEntry::InnerTree(BTreeMap::from([(
-                        'h',
-                        TreeEntry::InnerTree(BTreeMap::from([('t', TreeEntry::Number(8))])),
-                    )])),
-                )])),
-            )])),
-        ),
-        (
-            'n',
-            TreeEntry::InnerTree(BTreeMap::from([(
-                'i',
-                TreeEntry::InnerTree(BTreeMap::from([(
-                    'n',
-                    TreeEntry::InnerTree(BTreeMap::from([('e', TreeEntry::Number(9))])),
-                )])),
-            )])),
-        ),
-    ]);
-
-    return tree;
+pub fn part_two(args: Args) {
+    let vocabulary = args
+        .words
+        .as_deref()
+        .unwrap_or(ENGLISH)
+        .parse::<Vocabulary>()
+        .unwrap();
+    let scanner = DigitScanner::new(&vocabulary);
+    let sum = sum_calibration_values(&scanner).unwrap();
+    println!("{:?}", sum);
 }
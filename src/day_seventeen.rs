@@ -1,9 +1,20 @@
 use std::{
     cmp::Reverse,
-    collections::{BinaryHeap, HashSet},
+    collections::{BinaryHeap, HashMap},
 };
 
-use crate::Args;
+use itertools::Itertools;
+use plotters::{
+    coord::Shift,
+    prelude::*,
+    style::text_anchor::{HPos, Pos, VPos},
+};
+use prettytable::{row, Table};
+
+use crate::{
+    render::{render_to_file, Drawing},
+    Args,
+};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_seventeen.txt");
 
@@ -14,273 +25,473 @@ fn input() -> Vec<Vec<u32>> {
         .collect()
 }
 
-#[derive(Eq, Clone, Debug)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+enum Direction {
+    North = 0,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    fn opposite(&self) -> Self {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+
+    fn arrow(&self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<',
+        }
+    }
+
+    fn step(&self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        match self {
+            Direction::North => Some((x, y.checked_sub(1)?)),
+            Direction::East => Some((x + 1, y)),
+            Direction::South => Some((x, y + 1)),
+            Direction::West => Some((x.checked_sub(1)?, y)),
+        }
+    }
+}
+
+/// Where the crucible is, which way it last moved, and how many blocks it has moved that way
+/// in a row. A `run` of 0 only happens at the start, before the first move.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 struct State {
-    /// The cost plus the distance to the end
-    // priority: u64,
-    /// The actual heat lost
-    cost: u64,
-    /// How many steps have happened since the last turn?
-    forward_steps: u8,
-    /// How many steps have occurred since the start?
-    // steps: u64,
     position: (usize, usize),
-    previous: Option<Box<State>>,
-    came_from: Direction,
+    heading: Direction,
+    run: u8,
 }
 
-impl PartialEq for State {
-    fn eq(&self, other: &Self) -> bool {
-        self.position == other.position
-            && self.came_from == other.came_from
-            && self.forward_steps == other.forward_steps
-    }
+/// How the crucible is allowed to move.
+#[derive(Debug, Clone, Copy)]
+struct MovementRules {
+    /// Blocks the crucible has to move in a straight line before it can turn.
+    min_run: u8,
+    /// Blocks the crucible can move in a straight line before it has to turn.
+    max_run: u8,
+    /// Whether the crucible has to have moved `min_run` blocks before it can stop at the end.
+    stop_requires_min: bool,
+    /// Whether turning around counts as a turn instead of being forbidden.
+    allow_reverse: bool,
 }
 
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cost.cmp(&other.cost))
+impl std::fmt::Display for MovementRules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+        write!(
+            f,
+            "run {}..={}, stop requires min: {}, reverse: {}",
+            self.min_run,
+            self.max_run,
+            yes_no(self.stop_requires_min),
+            yes_no(self.allow_reverse)
+        )
     }
 }
 
-impl State {
-    pub fn came_from_direction(position: (usize, usize), prev: &State) -> Direction {
-        if prev.position.0 < position.0 {
-            return Direction::West;
-        } else if prev.position.0 > position.0 {
-            return Direction::East;
-        } else if prev.position.1 < position.1 {
-            return Direction::North;
-        } else {
-            return Direction::South;
+impl MovementRules {
+    pub fn crucible() -> Self {
+        Self {
+            min_run: 1,
+            max_run: 3,
+            stop_requires_min: false,
+            allow_reverse: false,
         }
     }
-}
 
-impl std::hash::Hash for State {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.position.hash(state);
-        state.write_u8(0);
-        self.came_from.hash(state);
-        state.write_u8(0);
-        self.forward_steps.hash(state);
+    pub fn ultra_crucible() -> Self {
+        Self {
+            min_run: 4,
+            max_run: 10,
+            stop_requires_min: true,
+            allow_reverse: false,
+        }
+    }
+
+    /// Applies any rule overrides given on the command line.
+    pub fn with_args(self, args: &Args) -> anyhow::Result<Self> {
+        let rules = Self {
+            min_run: args.min_run.unwrap_or(self.min_run),
+            max_run: args.max_run.unwrap_or(self.max_run),
+            stop_requires_min: args.stop_requires_min.unwrap_or(self.stop_requires_min),
+            allow_reverse: args.allow_reverse || self.allow_reverse,
+        };
+        if rules.max_run == 0 {
+            return Err(anyhow!("The crucible has to be able to move at least once"));
+        }
+        if rules.min_run > rules.max_run {
+            return Err(anyhow!(
+                "Minimum run {} is longer than the maximum run {}",
+                rules.min_run,
+                rules.max_run
+            ));
+        }
+        Ok(rules)
     }
-}
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.cost.cmp(&other.cost)
+    fn can_stop(&self, state: &State) -> bool {
+        !self.stop_requires_min || state.run == 0 || state.run >= self.min_run
     }
 }
 
-fn neighbors(input: &[Vec<u32>], state: &State, min: u8, max: u8) -> [Option<State>; 3] {
-    let previous = Some(Box::new(state.clone()));
-    let max_x = input[0].len() - 1;
-    let max_y = input.len() - 1;
-
-    let (diff_x, diff_y) = state.came_from.forward();
-
-    let mut neighbors = [None, None, None];
-    if state.forward_steps < max - 1 {
-        // Move forward
-        let forward = (
-            state.position.0 as isize + diff_x,
-            state.position.1 as isize + diff_y,
-        );
-        if forward.0 <= max_x as isize
-            && forward.0 >= 0
-            && forward.1 <= max_y as isize
-            && forward.1 >= 0
-        {
-            neighbors[0] = Some(State {
-                position: (forward.0 as usize, forward.1 as usize),
-                // priority: u64::MAX,
-                cost: u64::MAX,
-                forward_steps: state.forward_steps + 1,
-                // previous: Some(state_ptr.clone()),
-                // steps: state.steps + 1,
-                came_from: state.came_from,
-                previous: previous.clone(),
-            });
+#[derive(Debug, Clone)]
+struct Route {
+    cost: u64,
+    /// Every state from the start to the end, inclusive.
+    path: Vec<State>,
+}
+
+/// A straight stretch of the route between two turns.
+#[derive(Debug, Clone)]
+struct Segment {
+    from: (usize, usize),
+    to: (usize, usize),
+    heading: Direction,
+    blocks: u8,
+    heat_loss: u64,
+}
+
+impl Route {
+    pub fn segments(&self, input: &[Vec<u32>]) -> Vec<Segment> {
+        let mut segments: Vec<Segment> = vec![];
+        for (previous, state) in self.path.iter().tuple_windows() {
+            let (x, y) = state.position;
+            let heat_loss = input[y][x] as u64;
+            match segments.last_mut() {
+                Some(segment) if state.run > 1 => {
+                    segment.to = state.position;
+                    segment.blocks = state.run;
+                    segment.heat_loss += heat_loss;
+                }
+                _ => segments.push(Segment {
+                    from: previous.position,
+                    to: state.position,
+                    heading: state.heading,
+                    blocks: state.run,
+                    heat_loss,
+                }),
+            }
         }
+        segments
     }
 
-    let can_turn = state.forward_steps >= min - 1;
+    /// The heat loss digits with every block the route enters replaced by an arrow.
+    pub fn overlay(&self, input: &[Vec<u32>]) -> String {
+        let arrows = self
+            .path
+            .iter()
+            .skip(1)
+            .map(|s| (s.position, s.heading.arrow()))
+            .collect::<HashMap<_, _>>();
+        input
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(x, heat)| {
+                        arrows
+                            .get(&(x, y))
+                            .copied()
+                            .unwrap_or_else(|| char::from_digit(*heat, 10).unwrap())
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+}
 
-    if !can_turn {
-        return neighbors;
+fn breakdown(segments: &[Segment]) -> Table {
+    let mut table = Table::new();
+    table.add_row(row![
+        "segment",
+        "from",
+        "to",
+        "heading",
+        "blocks",
+        "heat loss",
+        "total"
+    ]);
+    let mut total = 0;
+    for (i, segment) in segments.iter().enumerate() {
+        total += segment.heat_loss;
+        table.add_row(row![
+            i + 1,
+            format!("{:?}", segment.from),
+            format!("{:?}", segment.to),
+            format!("{:?}", segment.heading),
+            segment.blocks,
+            segment.heat_loss,
+            total
+        ]);
     }
+    table
+}
 
-    if diff_x != 0 {
-        // Move up and down.
-        let up = (state.position.0, state.position.1 as isize - 1);
-        if up.1 <= max_y as isize && up.1 >= 0 {
-            neighbors[1] = Some(State {
-                position: (up.0, up.1 as usize),
-                // priority: u64::MAX,
-                cost: u64::MAX,
-                forward_steps: 0,
-                // previous: Some(state_ptr.clone()),
-                // steps: state.steps + 1,
-                came_from: State::came_from_direction((up.0, up.1 as usize), state),
-                previous: previous.clone(),
-            });
-        }
-        let down = (state.position.0, state.position.1 as isize + 1);
-        if down.1 <= max_y as isize && down.1 >= 0 {
-            let position = (down.0, down.1 as usize);
-            neighbors[2] = Some(State {
-                position,
-                // priority: u64::MAX,
-                cost: u64::MAX,
-                forward_steps: 0,
-                // previous: Some(state_ptr.clone()),
-                // steps: state.steps + 1,
-                came_from: State::came_from_direction(position, state),
-                previous,
-            });
+const CELL_SIZE: u32 = 12;
+
+/// The city with blocks shaded by heat loss and the route drawn on top.
+struct RouteDrawing<'a> {
+    input: &'a [Vec<u32>],
+    route: &'a Route,
+    segments: Vec<Segment>,
+}
+
+impl Drawing for RouteDrawing<'_> {
+    fn size(&self) -> (u32, u32) {
+        (
+            self.input[0].len() as u32 * CELL_SIZE,
+            self.input.len() as u32 * CELL_SIZE,
+        )
+    }
+
+    fn draw<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>) -> anyhow::Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        let cell = CELL_SIZE as i32;
+        let center =
+            |(x, y): (usize, usize)| (x as i32 * cell + cell / 2, y as i32 * cell + cell / 2);
+        let digit_style = ("sans-serif", cell - 2)
+            .into_font()
+            .color(&BLACK)
+            .pos(Pos::new(HPos::Center, VPos::Center));
+
+        area.fill(&WHITE)?;
+        for (y, row) in self.input.iter().enumerate() {
+            for (x, heat) in row.iter().enumerate() {
+                let shade = 255 - (*heat as u8) * 20;
+                let (left, top) = (x as i32 * cell, y as i32 * cell);
+                area.draw(&Rectangle::new(
+                    [(left, top), (left + cell, top + cell)],
+                    RGBColor(255, shade, shade).filled(),
+                ))?;
+                area.draw(&Text::new(heat.to_string(), center((x, y)), &digit_style))?;
+            }
         }
-    } else {
-        // Move left and right.
-        let left = (state.position.0 as isize - 1, state.position.1);
-        if left.0 >= 0 && left.0 <= max_x as isize {
-            let position = (left.0 as usize, left.1);
-            neighbors[1] = Some(State {
-                position,
-                // priority: u64::MAX,
-                cost: u64::MAX,
-                forward_steps: 0,
-                // previous: Some(state_ptr.clone()),
-                // steps: state.steps + 1,
-                came_from: State::came_from_direction(position, state),
-                previous: previous.clone(),
-            });
+
+        area.draw(&PathElement::new(
+            self.route
+                .path
+                .iter()
+                .map(|s| center(s.position))
+                .collect_vec(),
+            BLUE.stroke_width(3),
+        ))?;
+        for state in self.route.path.iter().skip(1) {
+            area.draw(&Text::new(
+                state.heading.arrow().to_string(),
+                center(state.position),
+                digit_style.color(&WHITE),
+            ))?;
         }
-        let right = (state.position.0 as isize + 1, state.position.1);
-        if right.0 >= 0 && right.0 <= max_x as isize {
-            let position = (right.0 as usize, right.1);
-            neighbors[2] = Some(State {
-                position,
-                // priority: u64::MAX,
-                cost: u64::MAX,
-                forward_steps: 0,
-                // previous: Some(state_ptr.clone()),
-                // steps: state.steps + 1,
-                came_from: State::came_from_direction(position, state),
-                previous,
-            });
+
+        let label_style = ("sans-serif", cell).into_font().color(&BLACK);
+        for segment in &self.segments {
+            let (from, to) = (center(segment.from), center(segment.to));
+            let middle = (
+                (from.0 + to.0) / 2 + cell / 2,
+                (from.1 + to.1) / 2 + cell / 2,
+            );
+            area.draw(&Text::new(
+                segment.heat_loss.to_string(),
+                middle,
+                &label_style,
+            ))?;
         }
+        Ok(())
     }
-
-    neighbors
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Clone, Copy)]
-enum Direction {
-    North = 0,
-    East,
-    South,
-    West,
+/// Dijkstra over a dense `dist[y][x][heading][run]` table, flattened into a `Vec`.
+struct Search<'a> {
+    input: &'a [Vec<u32>],
+    rules: MovementRules,
+    width: usize,
+    dist: Vec<u64>,
+    parent: Vec<Option<usize>>,
 }
 
-impl Direction {
-    fn forward(&self) -> (isize, isize) {
-        match self {
-            Direction::North => (0, 1),
-            Direction::East => (-1, 0),
-            Direction::South => (0, -1),
-            Direction::West => (1, 0),
+impl<'a> Search<'a> {
+    pub fn new(input: &'a [Vec<u32>], rules: MovementRules) -> Self {
+        let width = input[0].len();
+        let states = input.len() * width * Direction::ALL.len() * (rules.max_run as usize + 1);
+        Self {
+            input,
+            rules,
+            width,
+            dist: vec![u64::MAX; states],
+            parent: vec![None; states],
         }
     }
-}
 
-fn a_star(
-    input: &[Vec<u32>],
-    start: (usize, usize),
-    end: (usize, usize),
-    min: u8,
-    max: u8,
-) -> State {
-    let capacity = 4 * 4 * input.len() * input[0].len();
-    let mut open = BinaryHeap::with_capacity(capacity * 5);
-    open.push(Reverse(State {
-        cost: 0 as u64,
-        position: start,
-        forward_steps: 0,
-        // previous: None,
-        // priority: 0,
-        // steps: 0,
-        came_from: Direction::West,
-        previous: None,
-    }));
-    let mut closed = HashSet::<State>::with_capacity(capacity);
-
-    while let Some(current) = open.pop() {
-        let current = current.0;
-
-        if current.position == end {
-            println!("Found a solution: {}", current.cost);
-            return current;
+    fn index(&self, state: &State) -> usize {
+        let (x, y) = state.position;
+        let runs = self.rules.max_run as usize + 1;
+        ((y * self.width + x) * Direction::ALL.len() + state.heading as usize) * runs
+            + state.run as usize
+    }
+
+    fn state(&self, index: usize) -> State {
+        let runs = self.rules.max_run as usize + 1;
+        let run = (index % runs) as u8;
+        let index = index / runs;
+        let heading = Direction::ALL[index % Direction::ALL.len()];
+        let cell = index / Direction::ALL.len();
+        State {
+            position: (cell % self.width, cell / self.width),
+            heading,
+            run,
         }
+    }
 
-        if let Some(s) = closed.get(&current) {
-            // Already gone through this node
-            if s.cost <= current.cost {
-                continue;
+    fn neighbors(&self, state: &State) -> impl Iterator<Item = State> + '_ {
+        let state = *state;
+        Direction::ALL.into_iter().filter_map(move |heading| {
+            let run = if state.run == 0 {
+                1
+            } else if heading == state.heading {
+                if state.run >= self.rules.max_run {
+                    return None;
+                }
+                state.run + 1
+            } else if state.run < self.rules.min_run
+                || (heading == state.heading.opposite() && !self.rules.allow_reverse)
+            {
+                return None;
+            } else {
+                1
+            };
+
+            let position = heading.step(state.position)?;
+            if position.0 >= self.width || position.1 >= self.input.len() {
+                return None;
             }
-        }
+            Some(State {
+                position,
+                heading,
+                run,
+            })
+        })
+    }
 
-        let neighbors = neighbors(input, &current, min, max)
-            .into_iter()
-            .filter_map(|s| s);
-        for mut neighbor in neighbors {
-            // neighbor.previous = Some(Rc::new(RefCell::new(current.clone())));
-            neighbor.cost = current.cost + input[neighbor.position.1][neighbor.position.0] as u64;
+    pub fn run(mut self, start: (usize, usize), end: (usize, usize)) -> Option<Route> {
+        let mut open = BinaryHeap::new();
+        let start = self.index(&State {
+            position: start,
+            heading: Direction::East,
+            run: 0,
+        });
+        self.dist[start] = 0;
+        open.push(Reverse((0, start)));
+
+        while let Some(Reverse((cost, index))) = open.pop() {
+            if cost > self.dist[index] {
+                // A cheaper way to this state was already expanded.
+                continue;
+            }
 
-            // println!("Did not get {:?}", neighbor);
+            let current = self.state(index);
+            if current.position == end && self.rules.can_stop(&current) {
+                return Some(self.route(index));
+            }
 
-            open.push(Reverse(neighbor.clone()));
+            for neighbor in self.neighbors(&current).collect::<Vec<_>>() {
+                let (x, y) = neighbor.position;
+                let neighbor_cost = cost + self.input[y][x] as u64;
+                let neighbor_index = self.index(&neighbor);
+                if neighbor_cost < self.dist[neighbor_index] {
+                    self.dist[neighbor_index] = neighbor_cost;
+                    self.parent[neighbor_index] = Some(index);
+                    open.push(Reverse((neighbor_cost, neighbor_index)));
+                }
+            }
         }
 
-        closed.insert(current);
+        None
     }
 
-    unreachable!()
+    fn route(&self, end: usize) -> Route {
+        let mut path = vec![self.state(end)];
+        let mut current = self.parent[end];
+        while let Some(index) = current {
+            path.push(self.state(index));
+            current = self.parent[index];
+        }
+        path.reverse();
+
+        Route {
+            cost: self.dist[end],
+            path,
+        }
+    }
 }
 
-fn shortest_path(input: &[Vec<u32>], min: u8, max: u8) -> u64 {
-    let ending_position = (input[0].len() - 1, input.len() - 1);
-    let state = a_star(input, (0, 0), ending_position, min, max);
+fn shortest_path(input: &[Vec<u32>], rules: MovementRules, args: &Args) -> anyhow::Result<Route> {
+    let (width, height) = (input[0].len(), input.len());
+    let start = args.start.unwrap_or((0, 0));
+    let end = args.end.unwrap_or((width - 1, height - 1));
+    for (name, (x, y)) in [("Start", start), ("End", end)] {
+        if x >= width || y >= height {
+            return Err(anyhow!("{} ({}, {}) is outside the city", name, x, y));
+        }
+    }
 
-    let mut path = vec![state.position];
-    let mut current = state.previous.clone();
+    println!("Rules: {}", rules);
+    let route = Search::new(input, rules)
+        .run(start, end)
+        .ok_or_else(|| anyhow!("There is no path from {:?} to {:?}", start, end))?;
+    println!("Found a solution: {}", route.cost);
 
-    while let Some(prev) = current {
-        path.push(prev.position);
-        current = prev.previous.clone();
+    if let Some(last) = route.path.last() {
+        println!("Final straight run: {} blocks", last.run);
     }
 
-    println!("{:#?}", path);
+    Ok(route)
+}
 
-    for y in 0..input.len() {
-        for x in 0..input[0].len() {
-            if path.contains(&(x, y)) {
-                print!("=");
-            } else {
-                print!(".");
-            }
-        }
-        print!("\n");
+fn show(input: &[Vec<u32>], route: &Route, args: &Args) {
+    let segments = route.segments(input);
+    println!("{}", route.overlay(input));
+    println!("{}", breakdown(&segments));
+
+    if let Some(path) = &args.render {
+        let drawing = RouteDrawing {
+            input,
+            route,
+            segments,
+        };
+        render_to_file(path, &drawing).unwrap();
     }
-
-    state.cost
 }
 
-pub fn part_one(_args: Args) {
+pub fn part_one(args: Args) {
     let input = input();
-    println!("Cost: {}", shortest_path(&input, 1, 3));
+    let rules = MovementRules::crucible().with_args(&args).unwrap();
+    let route = shortest_path(&input, rules, &args).unwrap();
+    show(&input, &route, &args);
+    println!("Cost: {}", route.cost);
 }
-pub fn part_two(_args: Args) {
+pub fn part_two(args: Args) {
     let input = input();
-    println!("Cost: {}", shortest_path(&input, 4, 10));
+    let rules = MovementRules::ultra_crucible().with_args(&args).unwrap();
+    let route = shortest_path(&input, rules, &args).unwrap();
+    show(&input, &route, &args);
+    println!("Cost: {}", route.cost);
 }
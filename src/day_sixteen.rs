@@ -1,396 +1,354 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-use itertools::Itertools;
+use itertools::{Itertools, MinMaxResult};
+use plotters::{coord::Shift, prelude::*};
+use rayon::prelude::*;
 
-use crate::Args;
+use crate::{
+    render::{render_to_file, Drawing},
+    Args,
+};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_sixteen.txt");
 
-#[derive(Default, Debug, Clone)]
-struct Tile {
-    pub typ: TileType,
-    pub energized: bool,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    North,
+    East,
+    South,
+    West,
 }
 
-impl From<u8> for Tile {
-    fn from(value: u8) -> Self {
-        Self {
-            typ: TileType::from(value),
-            energized: false,
+impl Direction {
+    /// The bit used for this direction in `Energized::visited`.
+    fn bit(&self) -> u8 {
+        match self {
+            Direction::North => 0b0001,
+            Direction::East => 0b0010,
+            Direction::South => 0b0100,
+            Direction::West => 0b1000,
         }
     }
-}
 
-impl Display for Tile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.energized {
-            true => write!(f, "#"),
-            false => write!(f, "."),
+    fn step(&self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        match self {
+            Direction::North => Some((x, y.checked_sub(1)?)),
+            Direction::East => Some((x + 1, y)),
+            Direction::South => Some((x, y + 1)),
+            Direction::West => Some((x.checked_sub(1)?, y)),
         }
     }
 }
 
-impl Tile {
-    fn visit(&mut self, from: Direction) -> Action {
-        self.energized = true;
-
-        match &mut self.typ {
-            TileType::Vertical {
-                bottom_visited,
-                top_visited,
-                left_split,
-                right_split,
-            } => match from {
-                Direction::North => {
-                    if *top_visited {
-                        return Action::Stop;
-                    }
-
-                    *top_visited = true;
-                    Action::Direction(Direction::South)
-                }
-                Direction::South => {
-                    if *bottom_visited {
-                        return Action::Stop;
-                    }
-                    *bottom_visited = true;
-                    Action::Direction(Direction::North)
-                }
-                Direction::East => {
-                    if *right_split {
-                        return Action::Stop;
-                    }
-                    *right_split = true;
-                    Action::Split(Direction::North, Direction::South)
-                }
-                Direction::West => {
-                    if *left_split {
-                        return Action::Stop;
-                    }
-                    *left_split = true;
-                    Action::Split(Direction::North, Direction::South)
-                }
-            },
-            TileType::Horizontal {
-                left_visited,
-                right_visited,
-                bottom_split,
-                top_split,
-            } => {
-                // do the same thing as vertical, but for hoizontal
-                match from {
-                    Direction::North => {
-                        if *top_split {
-                            return Action::Stop;
-                        }
-                        *top_split = true;
-                        Action::Split(Direction::East, Direction::West)
-                    }
-                    Direction::South => {
-                        if *bottom_split {
-                            return Action::Stop;
-                        }
-                        *bottom_split = true;
-                        Action::Split(Direction::East, Direction::West)
-                    }
-                    Direction::East => {
-                        if *right_visited {
-                            return Action::Stop;
-                        }
-                        *right_visited = true;
-                        Action::Direction(Direction::West)
-                    }
-                    Direction::West => {
-                        if *left_visited {
-                            return Action::Stop;
-                        }
-                        *left_visited = true;
-                        Action::Direction(Direction::East)
-                    }
-                }
-            }
-            TileType::ReflectForward {
-                reflect_top,
-                reflect_right,
-                reflect_bottom,
-                reflect_left,
-            } => match from {
-                Direction::North => {
-                    if *reflect_top {
-                        return Action::Stop;
-                    }
-                    *reflect_top = true;
-                    Action::Direction(Direction::West)
-                }
-                Direction::South => {
-                    if *reflect_bottom {
-                        return Action::Stop;
-                    }
-                    *reflect_bottom = true;
-                    Action::Direction(Direction::East)
-                }
-                Direction::East => {
-                    if *reflect_right {
-                        return Action::Stop;
-                    }
-                    *reflect_right = true;
-                    Action::Direction(Direction::South)
-                }
-                Direction::West => {
-                    if *reflect_left {
-                        return Action::Stop;
-                    }
-                    *reflect_left = true;
-                    Action::Direction(Direction::North)
-                }
-            },
-            TileType::ReflectBack {
-                reflect_top,
-                reflect_right,
-                reflect_bottom,
-                reflect_left,
-            } => match from {
-                Direction::North => {
-                    if *reflect_top {
-                        return Action::Stop;
-                    }
-                    *reflect_top = true;
-                    Action::Direction(Direction::East)
-                }
-                Direction::South => {
-                    if *reflect_bottom {
-                        return Action::Stop;
-                    }
-                    *reflect_bottom = true;
-                    Action::Direction(Direction::West)
-                }
-                Direction::East => {
-                    if *reflect_right {
-                        return Action::Stop;
-                    }
-                    *reflect_right = true;
-                    Action::Direction(Direction::North)
-                }
-                Direction::West => {
-                    if *reflect_left {
-                        return Action::Stop;
-                    }
-                    *reflect_left = true;
-                    Action::Direction(Direction::South)
-                }
-            },
-            TileType::Empty => Action::Direction(from.opposite()),
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum TileType {
+    /// `|`
+    Vertical,
+    /// `-`
+    Horizontal,
+    /// `/`
+    ReflectForward,
+    /// `\`
+    ReflectBack,
+    #[default]
+    Empty,
+}
+
+impl TryFrom<u8> for TileType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use TileType::*;
+        match value {
+            b'.' => Ok(Empty),
+            b'/' => Ok(ReflectForward),
+            b'\\' => Ok(ReflectBack),
+            b'-' => Ok(Horizontal),
+            b'|' => Ok(Vertical),
+            _ => Err(anyhow!("Unknown tile {:?}", value as char)),
         }
     }
 }
 
-enum Action {
-    Stop,
-    Split(Direction, Direction),
-    Direction(Direction),
+impl TileType {
+    /// The directions a beam leaves this tile in, given the direction it was moving when it entered.
+    fn outgoing(&self, heading: Direction) -> (Direction, Option<Direction>) {
+        use Direction::*;
+        match (self, heading) {
+            (TileType::Vertical, East | West) => (North, Some(South)),
+            (TileType::Horizontal, North | South) => (East, Some(West)),
+            (TileType::ReflectForward, North) => (East, None),
+            (TileType::ReflectForward, East) => (North, None),
+            (TileType::ReflectForward, South) => (West, None),
+            (TileType::ReflectForward, West) => (South, None),
+            (TileType::ReflectBack, North) => (West, None),
+            (TileType::ReflectBack, East) => (South, None),
+            (TileType::ReflectBack, South) => (East, None),
+            (TileType::ReflectBack, West) => (North, None),
+            (_, heading) => (heading, None),
+        }
+    }
 }
 
-#[derive(Debug)]
-enum Direction {
-    North,
-    East,
-    South,
-    West,
+/// The static mirror and splitter layout.
+#[derive(Debug, Clone)]
+struct Contraption {
+    tiles: Vec<Vec<TileType>>,
+    width: usize,
+    height: usize,
 }
 
-impl Direction {
-    fn opposite(&self) -> Self {
-        use Direction::*;
-        match self {
-            North => South,
-            South => North,
-            East => West,
-            West => East,
+impl FromStr for Contraption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tiles = s
+            .lines()
+            .map(|line| line.bytes().map(TileType::try_from).collect())
+            .collect::<anyhow::Result<Vec<Vec<_>>>>()?;
+        let width = tiles.first().map(|row| row.len()).unwrap_or(0);
+        if tiles.iter().any(|row| row.len() != width) {
+            return Err(anyhow!("Contraption rows have different lengths"));
         }
+
+        Ok(Self {
+            height: tiles.len(),
+            width,
+            tiles,
+        })
     }
 }
 
-#[derive(Default, Debug, Clone)]
-enum TileType {
-    Vertical {
-        bottom_visited: bool,
-        top_visited: bool,
-        left_split: bool,
-        right_split: bool,
-    },
-    Horizontal {
-        left_visited: bool,
-        right_visited: bool,
-        bottom_split: bool,
-        top_split: bool,
-    },
-    ReflectForward {
-        reflect_top: bool,
-        reflect_right: bool,
-        reflect_bottom: bool,
-        reflect_left: bool,
-    },
-    ReflectBack {
-        reflect_top: bool,
-        reflect_right: bool,
-        reflect_bottom: bool,
-        reflect_left: bool,
-    },
-    #[default]
-    Empty,
+/// The directions beams were moving in when they entered each tile.
+#[derive(Debug, Clone)]
+struct Energized {
+    /// One `Direction::bit` flag per direction, one byte per tile.
+    visited: Vec<u8>,
+    width: usize,
 }
 
-impl From<u8> for TileType {
-    fn from(value: u8) -> Self {
-        use TileType::*;
-        match value {
-            b'.' => Empty,
-            b'/' => ReflectForward {
-                reflect_top: false,
-                reflect_right: false,
-                reflect_bottom: false,
-                reflect_left: false,
-            },
-            b'\\' => ReflectBack {
-                reflect_top: false,
-                reflect_right: false,
-                reflect_bottom: false,
-                reflect_left: false,
-            },
-            b'-' => Horizontal {
-                left_visited: false,
-                right_visited: false,
-                bottom_split: false,
-                top_split: false,
-            },
-            b'|' => Vertical {
-                bottom_visited: false,
-                top_visited: false,
-                left_split: false,
-                right_split: false,
-            },
-            _ => unreachable!(),
-        }
+impl Energized {
+    pub fn directions(&self, (x, y): (usize, usize)) -> u8 {
+        self.visited[y * self.width + x]
+    }
+
+    pub fn count(&self) -> usize {
+        self.visited.iter().filter(|v| **v != 0).count()
     }
 }
 
-fn input() -> Vec<Vec<Tile>> {
-    FILE_CONTENTS
-        .lines()
-        .map(|line| {
-            line.bytes()
-                .map(|byte| Tile::from(byte))
-                .collect::<Vec<_>>()
-        })
-        .collect::<Vec<_>>()
+impl Display for Energized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = self.visited.chunks(self.width).map(|row| {
+            row.iter()
+                .map(|v| if *v != 0 { '#' } else { '.' })
+                .collect::<String>()
+        });
+        write!(f, "{}", rows.format("\n"))
+    }
 }
 
-fn walk(input: &mut [Vec<Tile>], mut current: (isize, isize), mut heading_toward: Direction) {
-    let x_len = input.len();
-    let y_len = input[0].len();
-    loop {
-        let (x, y) = current;
-        if x < 0 || x >= x_len as isize || y < 0 || y >= y_len as isize {
-            println!("Stopped at edge: {:?}", current);
-            return;
-        }
-        let tile = &mut input[y as usize][x as usize];
-        let action = tile.visit(heading_toward.opposite());
+impl Contraption {
+    /// Follows every beam from `start` without recursion, stopping a beam once it enters a
+    /// tile in a direction some other beam already took.
+    pub fn energize(&self, start: (usize, usize), heading: Direction) -> Energized {
+        let mut energized = Energized {
+            visited: vec![0; self.width * self.height],
+            width: self.width,
+        };
+        let mut beams = vec![(start, heading)];
 
-        match action {
-            Action::Stop => {
-                println!("Stopped at {:?}", current);
-                break;
+        while let Some(((x, y), heading)) = beams.pop() {
+            if x >= self.width || y >= self.height {
+                continue;
             }
-            Action::Split(left, right) => {
-                println!("Splitting at {:?}", current);
-                walk(input, (x, y), left);
-                walk(input, (x, y), right);
-                return;
+            let visited = &mut energized.visited[y * self.width + x];
+            if *visited & heading.bit() != 0 {
+                continue;
             }
-            Action::Direction(direction) => {
-                println!("Moving {:?} from {:?}", direction, current);
-                match direction {
-                    Direction::North => current.1 -= 1,
-                    Direction::South => current.1 += 1,
-                    Direction::East => current.0 += 1,
-                    Direction::West => current.0 -= 1,
+            *visited |= heading.bit();
+
+            let (first, second) = self.tiles[y][x].outgoing(heading);
+            for direction in std::iter::once(first).chain(second) {
+                if let Some(next) = direction.step((x, y)) {
+                    beams.push((next, direction));
                 }
-                heading_toward = direction;
             }
         }
+
+        energized
+    }
+
+    /// Every tile on the edge paired with the direction that points into the contraption.
+    pub fn edge_starts(&self) -> Vec<((usize, usize), Direction)> {
+        let (right, bottom) = (self.width - 1, self.height - 1);
+        (0..self.width)
+            .flat_map(|x| [((x, 0), Direction::South), ((x, bottom), Direction::North)])
+            .chain(
+                (0..self.height)
+                    .flat_map(|y| [((0, y), Direction::East), ((right, y), Direction::West)]),
+            )
+            .collect_vec()
     }
 }
 
-fn debug(input: &[Vec<Tile>]) -> String {
-    input
-        .iter()
-        .map(|row| row.iter().map(|t| format!("{}", t)).join(""))
-        .join("\n")
+const CELL_SIZE: u32 = 8;
+const TITLE_SIZE: u32 = 30;
+/// Tile colors for 0 through 4 beam directions passing through.
+const HEAT: [RGBColor; 5] = [
+    WHITE,
+    RGBColor(255, 237, 160),
+    RGBColor(254, 178, 76),
+    RGBColor(240, 59, 32),
+    RGBColor(140, 0, 20),
+];
+
+struct Run {
+    label: String,
+    start: (usize, usize),
+    energized: Energized,
 }
 
-fn count_tiles(input: &[Vec<Tile>]) -> usize {
-    input
-        .iter()
-        .map(|r| r.iter().filter(|t| t.energized).count())
-        .sum::<usize>()
+/// One heatmap panel per run, laid out left to right.
+struct Heatmap<'a> {
+    contraption: &'a Contraption,
+    runs: Vec<Run>,
 }
 
-pub fn part_one(_args: Args) {
-    let mut input = input();
+impl Heatmap<'_> {
+    fn draw_run<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+        run: &Run,
+    ) -> anyhow::Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        let (title, grid) = area.split_vertically(TITLE_SIZE);
+        title.draw(&Text::new(run.label.as_str(), (5, 5), ("sans-serif", 18)))?;
 
-    let current = (0, 0);
-    let heading_toward = Direction::East;
-    walk(&mut input, current, heading_toward);
-    println!("{}", debug(&input));
+        let cell = CELL_SIZE as i32;
+        for (y, row) in self.contraption.tiles.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let heat = run.energized.directions((x, y)).count_ones() as usize;
+                let (left, top) = (x as i32 * cell, y as i32 * cell);
+                let (right, bottom) = (left + cell, top + cell);
+                let (mid_x, mid_y) = (left + cell / 2, top + cell / 2);
+                grid.draw(&Rectangle::new(
+                    [(left, top), (right, bottom)],
+                    HEAT[heat].filled(),
+                ))?;
 
-    let count = count_tiles(&input);
-    println!("Count: {}", count);
-}
+                let glyph = match tile {
+                    TileType::Vertical => [(mid_x, top), (mid_x, bottom)],
+                    TileType::Horizontal => [(left, mid_y), (right, mid_y)],
+                    TileType::ReflectForward => [(left, bottom), (right, top)],
+                    TileType::ReflectBack => [(left, top), (right, bottom)],
+                    TileType::Empty => continue,
+                };
+                grid.draw(&PathElement::new(glyph, BLACK.stroke_width(1)))?;
+            }
+        }
 
-pub fn part_two(_args: Args) {
-    let input = input();
-    let x_len = input.len();
-    let y_len = input[0].len();
+        let (x, y) = run.start;
+        grid.draw(&Circle::new(
+            (x as i32 * cell + cell / 2, y as i32 * cell + cell / 2),
+            cell / 2,
+            BLUE.filled(),
+        ))?;
+        Ok(())
+    }
+}
 
-    let mut largest = 0;
+impl Drawing for Heatmap<'_> {
+    fn size(&self) -> (u32, u32) {
+        (
+            self.contraption.width as u32 * CELL_SIZE * self.runs.len() as u32,
+            self.contraption.height as u32 * CELL_SIZE + TITLE_SIZE,
+        )
+    }
 
-    for x in 0..x_len {
-        {
-            let mut input = input.clone();
-            walk(&mut input, (x as isize, 0), Direction::South);
-            largest = std::cmp::max(largest, count_tiles(&input));
-        }
-        {
-            let mut input = input.clone();
-            walk(
-                &mut input,
-                (x as isize, (y_len - 1) as isize),
-                Direction::North,
-            );
-            largest = std::cmp::max(largest, count_tiles(&input));
+    fn draw<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>) -> anyhow::Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        area.fill(&WHITE)?;
+        let panels = area.split_evenly((1, self.runs.len()));
+        for (panel, run) in panels.iter().zip(&self.runs) {
+            self.draw_run(panel, run)?;
         }
+        Ok(())
     }
+}
 
-    for y in 0..y_len {
-        {
-            let mut input = input.clone();
-            walk(&mut input, (0 as isize, y as isize), Direction::East);
-            largest = std::cmp::max(largest, count_tiles(&input));
-        }
-        {
-            let mut input = input.clone();
-            walk(
-                &mut input,
-                ((x_len - 1) as isize, y as isize),
-                Direction::West,
-            );
-            largest = std::cmp::max(largest, count_tiles(&input));
-        }
+fn input() -> Contraption {
+    FILE_CONTENTS.parse().unwrap()
+}
+
+pub fn part_one(args: Args) {
+    let contraption = input();
+
+    let start = (0, 0);
+    let energized = contraption.energize(start, Direction::East);
+    println!("{}", energized);
+
+    println!("Count: {}", energized.count());
+
+    if let Some(path) = &args.render {
+        let heatmap = Heatmap {
+            contraption: &contraption,
+            runs: vec![Run {
+                label: format!("{} tiles energized", energized.count()),
+                start,
+                energized,
+            }],
+        };
+        render_to_file(path, &heatmap).unwrap();
     }
+}
+
+pub fn part_two(args: Args) {
+    let contraption = input();
+
+    let counts = contraption
+        .edge_starts()
+        .into_par_iter()
+        .map(|(start, heading)| {
+            let count = contraption.energize(start, heading).count();
+            (start, heading, count)
+        })
+        .collect::<Vec<_>>();
+    let (best, worst) = match counts.iter().minmax_by_key(|(_, _, count)| *count) {
+        MinMaxResult::MinMax(worst, best) => (*best, *worst),
+        MinMaxResult::OneElement(only) => (*only, *only),
+        MinMaxResult::NoElements => panic!("The contraption has no edges"),
+    };
 
     println!(
         "Most energized layout causes {} tiles to be energized",
-        largest
+        best.2
+    );
+    println!(
+        "Least energized layout enters at {:?} heading {:?} and energizes {} tiles",
+        worst.0, worst.1, worst.2
     );
+
+    if let Some(path) = &args.render {
+        let runs = [("Best", best), ("Worst", worst)]
+            .into_iter()
+            .map(|(name, (start, heading, count))| Run {
+                label: format!(
+                    "{}: enter {:?} heading {:?}, {} tiles energized",
+                    name, start, heading, count
+                ),
+                start,
+                energized: contraption.energize(start, heading),
+            })
+            .collect_vec();
+        let heatmap = Heatmap {
+            contraption: &contraption,
+            runs,
+        };
+        render_to_file(path, &heatmap).unwrap();
+    }
 }
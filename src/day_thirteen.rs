@@ -1,247 +1,81 @@
 use itertools::Itertools;
 
-use crate::Args;
+use crate::{bit_grid::BitGrid, Args};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_thirteen.txt");
 
-fn input(input: &str) -> Vec<(Vec<String>, Vec<String>)> {
-    let patterns = input
+fn input(input: &str) -> anyhow::Result<Vec<(BitGrid, BitGrid)>> {
+    input
         .split("\n\n")
-        .map(|pattern| breakout_pattern(pattern))
-        .collect_vec();
-
-    patterns
-}
-
-fn breakout_pattern(pattern: &str) -> (Vec<String>, Vec<String>) {
-    let rows = pattern.lines().map(|s| s.to_owned()).collect_vec();
-    let rows_slice = rows.as_slice();
-    let cols = (0..rows[0].len())
-        .map(move |i| {
-            String::from_utf8(
-                rows_slice
-                    .iter()
-                    .map(move |r| r.bytes().nth(i).unwrap())
-                    .collect_vec(),
-            )
-            .unwrap()
-        })
-        .collect_vec();
-    (rows, cols)
-}
-
-fn get_matches(slice: &[String]) -> Vec<usize> {
-    slice
-        .iter()
-        .enumerate()
-        .rev()
-        .skip(1)
-        .rev()
-        .filter_map(|(i, val)| {
-            if val.as_str() == slice[i + 1].as_str() {
-                return Some(i);
-            }
-
-            None
+        .map(|pattern| {
+            let rows = BitGrid::parse(pattern, b'#')?;
+            let cols = rows.transpose();
+            Ok((rows, cols))
         })
-        .collect_vec()
-}
-
-fn is_reflection(iter: &[String], left: usize) -> bool {
-    // walk until left == right
-    // left += 1 (wrap with length) and right -= 1 (wrap with length)
-    // ensure that iter[left] == iter[right]
-
-    let right = left + 1;
-
-    for i in 0..iter.len() {
-        let left_str = iter[left - i].as_str();
-        let right_str = iter[right + i].as_str();
-
-        if left_str != right_str {
-            return false;
-        }
-
-        if left - i == 0 || right + i == iter.len() - 1 {
-            return true;
-        }
-    }
-
-    return false;
-}
-
-fn symmetry(iter: &[String]) -> Option<usize> {
-    let matches = get_matches(iter);
-
-    for possible_symmetry in matches {
-        if is_reflection(iter, possible_symmetry) {
-            return Some(possible_symmetry + 1);
-        }
-    }
-
-    None
+        .collect()
 }
 
-fn row_symmetry_p2(rows: &[String], original: usize) -> Option<usize> {
-    let matches = get_matches(rows);
-
-    for possible_symmetry in matches {
-        if is_reflection(rows, possible_symmetry) {
-            if original >= 100 && (possible_symmetry + 1) * 100 == original {
-                continue;
-            }
-            return Some((possible_symmetry + 1) * 100);
-        }
-    }
-
-    None
+/// A line of reflection, given as the number of rows above it or columns left of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reflection {
+    Row(usize),
+    Column(usize),
 }
 
-fn col_symmetry_p2(cols: &[String], original: usize) -> Option<usize> {
-    let matches = get_matches(cols);
-
-    for possible_symmetry in matches {
-        if is_reflection(cols, possible_symmetry) {
-            if original < 100 && possible_symmetry + 1 == original {
-                continue;
-            }
-            return Some(possible_symmetry + 1);
+impl Reflection {
+    pub fn summary(&self) -> usize {
+        match self {
+            Reflection::Row(above) => above * 100,
+            Reflection::Column(left) => *left,
         }
     }
-
-    None
-}
-
-fn row_symmetry(rows: &[String]) -> Option<usize> {
-    symmetry(rows).map(|left| left * 100)
-}
-
-fn col_symmetry(cols: &[String]) -> Option<usize> {
-    symmetry(cols)
 }
 
-fn solve(rows: &[String], cols: &[String]) -> Option<usize> {
-    if let Some(num) = col_symmetry(cols) {
-        return Some(num);
-    }
-
-    if let Some(num) = row_symmetry(rows) {
-        return Some(num);
-    }
-
-    return None;
+/// Counts the cells that differ when `lines` is folded between `axis - 1` and `axis`.
+fn mismatches(lines: &[u128], axis: usize) -> usize {
+    lines[..axis]
+        .iter()
+        .rev()
+        .zip(&lines[axis..])
+        .map(|(left, right)| (left ^ right).count_ones() as usize)
+        .sum()
 }
 
-fn solve_p2(rows: &[String], cols: &[String], original: usize) -> Option<usize> {
-    if let Some(num) = col_symmetry_p2(cols, original) {
-        return Some(num);
-    }
-
-    if let Some(num) = row_symmetry_p2(rows, original) {
-        return Some(num);
-    }
-
-    return None;
+fn axes_with_mismatches(lines: &BitGrid, smudges: usize) -> impl Iterator<Item = usize> + '_ {
+    let lines = lines.rows();
+    (1..lines.len()).filter(move |axis| mismatches(lines, *axis) == smudges)
 }
 
-pub fn part_one(_args: Args) {
-    let input = input(FILE_CONTENTS);
-    let res = input
-        .into_iter()
-        .map(|(rows, cols)| {
-            let res = solve(&rows, &cols).unwrap();
-            if res == 0 {
-                panic!("Part 1 should never be 0");
-            }
-            res
-        })
-        .sum::<usize>();
-    println!("Sum: {}", res);
+/// Finds every line of reflection where exactly `smudges` cells would need to change
+/// for the pattern to be a perfect mirror image.
+fn reflections(rows: &BitGrid, cols: &BitGrid, smudges: usize) -> Vec<Reflection> {
+    axes_with_mismatches(cols, smudges)
+        .map(Reflection::Column)
+        .chain(axes_with_mismatches(rows, smudges).map(Reflection::Row))
+        .collect_vec()
 }
 
-pub fn part_two(_args: Args) {
-    let input = input(FILE_CONTENTS);
-    let res = input
+fn summarize(smudges: usize) -> usize {
+    input(FILE_CONTENTS)
+        .unwrap()
         .into_iter()
         .map(|(rows, cols)| {
-            let normal_solution = solve(&rows, &cols).unwrap();
-            let rows_with_one_difference = duplicate_with_differences(&rows);
-
-            for (diffed_row, diffed_col) in rows_with_one_difference {
-                let maybe_res = solve_p2(&diffed_row, &diffed_col, normal_solution);
-                if maybe_res.map(|res| res != normal_solution).unwrap_or(false) {
-                    return maybe_res.unwrap();
-                }
+            let found = reflections(&rows, &cols, smudges);
+            match found.as_slice() {
+                [reflection] => reflection.summary(),
+                _ => panic!(
+                    "Expected exactly one reflection with {} smudge(s), found {:?}",
+                    smudges, found
+                ),
             }
-
-            let cols_with_one_difference = duplicate_with_differences(&cols);
-
-            for (diffed_col, diffed_row) in cols_with_one_difference {
-                let maybe_res = solve_p2(&diffed_row, &diffed_col, normal_solution);
-                if maybe_res.map(|res| res != normal_solution).unwrap_or(false) {
-                    return maybe_res.unwrap();
-                }
-            }
-
-            unreachable!("Part 2 should never get here.");
         })
-        .sum::<usize>();
-    println!("Sum: {}", res);
+        .sum::<usize>()
 }
 
-fn duplicate_with_differences(iter: &[String]) -> Vec<(Vec<String>, Vec<String>)> {
-    let differences = iter
-        .iter()
-        .enumerate()
-        .filter_map(|(i, str_to_match)| {
-            let mut counter = Vec::new();
-            for (j, other) in iter.iter().map(|s| s.as_str()).enumerate() {
-                let differences = str_to_match
-                    .bytes()
-                    .zip(other.bytes())
-                    .enumerate()
-                    .filter(|(_, (left, right))| left != right)
-                    .collect_vec();
-
-                if differences.len() != 1 {
-                    continue;
-                }
-
-                counter.push((i, j, differences[0].0, differences[0].1));
-            }
-
-            if counter.len() == 0 {
-                return None;
-            }
-
-            Some(counter)
-        })
-        .flat_map(|f| f);
-
-    let mut res = Vec::new();
-    for (left_row, right_row, str_idx, (left_byte, right_byte)) in differences {
-        let left_str = iter[left_row].as_str();
-        let right_str = iter[right_row].as_str();
-
-        let mut left_swapped = iter.to_owned();
-        left_swapped[left_row] = swap(left_str, right_byte, str_idx);
-
-        let mut right_swapped = iter.to_owned();
-        right_swapped[right_row] = swap(right_str, left_byte, str_idx);
-
-        res.push(left_swapped);
-        res.push(right_swapped);
-    }
-
-    res.into_iter()
-        .map(|x| breakout_pattern(x.join("\n").as_str()))
-        .collect_vec()
+pub fn part_one(_args: Args) {
+    println!("Sum: {}", summarize(0));
 }
 
-fn swap(original: &str, byte: u8, index: usize) -> String {
-    let mut mutated = original.to_owned();
-    // SAFETY: I'll ensure it's always valid UTF-8
-    let buf = unsafe { mutated.as_bytes_mut() };
-    buf[index] = byte;
-    mutated
+pub fn part_two(_args: Args) {
+    println!("Sum: {}", summarize(1));
 }
@@ -1,5 +1,6 @@
 use dashmap::DashMap;
 use itertools::Itertools;
+use prettytable::{row, Table};
 use rayon::prelude::*;
 
 use crate::Args;
@@ -7,7 +8,7 @@ use crate::Args;
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_twelve.txt");
 
 lazy_static::lazy_static! {
-    static ref MEMOIZED: DashMap<u64, usize> = DashMap::new();
+    static ref MEMOIZED: DashMap<u64, u128> = DashMap::new();
 }
 
 fn read_input() -> Vec<(Vec<u8>, Vec<u8>)> {
@@ -26,7 +27,7 @@ fn read_input() -> Vec<(Vec<u8>, Vec<u8>)> {
         .collect_vec()
 }
 
-fn calc_memoized(bytes: &[u8], groups: &[u8]) -> usize {
+fn calc_memoized(bytes: &[u8], groups: &[u8]) -> u128 {
     use std::hash::Hasher;
     let mut hasher = std::collections::hash_map::DefaultHasher::new();
     hasher.write(bytes);
@@ -42,13 +43,13 @@ fn calc_memoized(bytes: &[u8], groups: &[u8]) -> usize {
     return res;
 }
 
-fn dot(bytes: &[u8], groups: &[u8]) -> usize {
+fn dot(bytes: &[u8], groups: &[u8]) -> u128 {
     let res = calc_memoized(&bytes[1..], groups);
     let _do_not_optimize = res + 1 - 1;
     res
 }
 
-fn hash(bytes: &[u8], groups: &[u8]) -> usize {
+fn hash(bytes: &[u8], groups: &[u8]) -> u128 {
     let curr_group_size = groups[0];
     let group: &[u8] = &bytes[..curr_group_size as usize];
     if group.iter().any(|c| c == &b'.') {
@@ -72,7 +73,7 @@ fn hash(bytes: &[u8], groups: &[u8]) -> usize {
     res
 }
 
-fn calc(bytes: &[u8], groups: &[u8]) -> usize {
+fn calc(bytes: &[u8], groups: &[u8]) -> u128 {
     if groups.len() == 0 {
         if bytes.contains(&b'#') {
             // There are no more groups but there are more hashes.
@@ -87,7 +88,13 @@ fn calc(bytes: &[u8], groups: &[u8]) -> usize {
         return 0;
     }
 
-    if bytes.len() < groups.iter().cloned().reduce(|acc, x| acc + 1 + x).unwrap() as usize {
+    if bytes.len()
+        < groups
+            .iter()
+            .map(|g| *g as usize)
+            .reduce(|acc, x| acc + 1 + x)
+            .unwrap()
+    {
         // The length of the string is too small to contain the groups
         return 0;
     }
@@ -104,39 +111,137 @@ fn calc(bytes: &[u8], groups: &[u8]) -> usize {
     res
 }
 
-fn solve(bytes: Vec<u8>, sequence: Vec<u8>) -> usize {
+fn solve(bytes: Vec<u8>, sequence: Vec<u8>) -> u128 {
     calc_memoized(&bytes, &sequence)
 }
 
-pub fn part_one(_args: Args) {
-    let input = read_input();
-    let sum = input
-        .into_par_iter()
-        .map(|(bytes, sequence)| solve(bytes, sequence))
-        .sum::<usize>();
-    println!("Sum: {}", sum);
+/// Repeats the record `factor` times joined by `?` and the groups `factor` times.
+fn unfold(bytes: &[u8], groups: &[u8], factor: usize) -> (Vec<u8>, Vec<u8>) {
+    let bytes = (0..factor).map(|_| bytes).collect_vec().join(&b'?');
+    (bytes, groups.repeat(factor))
+}
+
+/// Walks the same `dot`/`hash` recursion as `calc`, only descending into branches
+/// that have at least one arrangement, and collects up to `limit` of them.
+fn arrangements(bytes: &[u8], groups: &[u8], limit: usize) -> Vec<Vec<u8>> {
+    fn walk(
+        bytes: &[u8],
+        groups: &[u8],
+        prefix: &mut Vec<u8>,
+        found: &mut Vec<Vec<u8>>,
+        limit: usize,
+    ) {
+        if found.len() >= limit || calc_memoized(bytes, groups) == 0 {
+            return;
+        }
+
+        if groups.is_empty() {
+            let mut arrangement = prefix.clone();
+            arrangement.extend(bytes.iter().map(|_| b'.'));
+            found.push(arrangement);
+            return;
+        }
+
+        let first_byte = bytes[0];
+
+        if first_byte != b'#' && dot(bytes, groups) > 0 {
+            prefix.push(b'.');
+            walk(&bytes[1..], groups, prefix, found, limit);
+            prefix.pop();
+        }
+
+        if first_byte != b'.' && hash(bytes, groups) > 0 {
+            let size = groups[0] as usize;
+            let consumed = (size + 1).min(bytes.len());
+            let len = prefix.len();
+            prefix.extend(std::iter::repeat_n(b'#', size));
+            prefix.extend(std::iter::repeat_n(b'.', consumed - size));
+            walk(&bytes[consumed..], &groups[1..], prefix, found, limit);
+            prefix.truncate(len);
+        }
+    }
+
+    let mut found = vec![];
+    walk(bytes, groups, &mut vec![], &mut found, limit);
+    found
+}
+
+/// Colors damaged springs: red where the record already had `#`, yellow where a `?` was filled in.
+fn highlight(record: &[u8], arrangement: &[u8]) -> String {
+    record
+        .iter()
+        .zip(arrangement)
+        .map(|(original, spring)| match (original, spring) {
+            (b'#', _) => "\x1b[1;31m#\x1b[0m".to_string(),
+            (_, b'#') => "\x1b[1;33m#\x1b[0m".to_string(),
+            (_, spring) => (*spring as char).to_string(),
+        })
+        .collect()
+}
+
+fn explore(input: &[(Vec<u8>, Vec<u8>)], line: usize, limit: usize) {
+    let Some((bytes, groups)) = line.checked_sub(1).and_then(|i| input.get(i)) else {
+        println!("There is no record on line {}", line);
+        return;
+    };
+
+    println!(
+        "Line {}: {} {}",
+        line,
+        String::from_utf8_lossy(bytes),
+        groups.iter().join(",")
+    );
+    let found = arrangements(bytes, groups, limit);
+    for arrangement in &found {
+        println!("{}", highlight(bytes, arrangement));
+    }
+    let total = calc_memoized(bytes, groups);
+    if total > found.len() as u128 {
+        println!("... and {} more", total - found.len() as u128);
+    }
+
+    let mut table = Table::new();
+    table.add_row(row!["line", "record", "groups", "arrangements"]);
+    for (i, (bytes, groups)) in input.iter().enumerate() {
+        table.add_row(row![
+            i + 1,
+            String::from_utf8_lossy(bytes),
+            groups.iter().join(","),
+            calc_memoized(bytes, groups)
+        ]);
+    }
+    println!("{}", table);
+}
+
+fn unfold_factor(args: &Args, default_factor: usize) -> anyhow::Result<usize> {
+    match args.unfold.unwrap_or(default_factor) {
+        0 => Err(anyhow!("--unfold must be at least 1")),
+        factor => Ok(factor),
+    }
 }
 
-pub fn part_two(_args: Args) {
+fn run(args: Args, default_factor: usize) {
+    let factor = unfold_factor(&args, default_factor).unwrap();
     let input = read_input()
         .into_iter()
-        .map(|(bytes, seq)| ((0..5).map(move |_i| bytes.clone()), seq.repeat(5)))
-        .map(|(bytes, seq)| {
-            (
-                bytes
-                    .map(|part| String::from_utf8(part).unwrap())
-                    .into_iter()
-                    .join("?")
-                    .bytes()
-                    .collect_vec(),
-                seq,
-            )
-        })
+        .map(|(bytes, seq)| unfold(&bytes, &seq, factor))
         .collect_vec();
 
+    if let Some(line) = args.explore {
+        explore(&input, line, args.limit);
+    }
+
     let sum = input
         .into_par_iter()
         .map(|(bytes, sequence)| solve(bytes, sequence))
-        .sum::<usize>();
+        .sum::<u128>();
     println!("Sum: {}", sum);
 }
+
+pub fn part_one(args: Args) {
+    run(args, 1);
+}
+
+pub fn part_two(args: Args) {
+    run(args, 5);
+}
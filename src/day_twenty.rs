@@ -1,32 +1,49 @@
 use std::{
-    cell::RefCell,
     collections::{HashMap, VecDeque},
+    fmt::Display,
+    fs::File,
+    io::{BufWriter, Write},
     ops::Not,
-    rc::Rc,
     str::FromStr,
 };
 
-use itertools::Itertools;
 use num::Integer;
 
-use crate::Args;
+use crate::{render::write_dot, Args};
 
 const FILE_CONTENTS: &'static str = include_str!("../inputs/day_twenty.txt");
 
-static mut PRESSES: u64 = 0;
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-#[repr(u8)]
 enum Pulse {
-    Low = 0,
-    High = 1,
+    Low,
+    High,
 }
 
-#[derive(Debug, Clone, Copy)]
-#[repr(u8)]
+impl Display for Pulse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pulse::Low => write!(f, "low"),
+            Pulse::High => write!(f, "high"),
+        }
+    }
+}
+
+impl FromStr for Pulse {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Pulse::Low),
+            "high" => Ok(Pulse::High),
+            _ => Err(anyhow!("Expected a low or high pulse, not {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum FlipFlop {
-    Off = 0,
-    On = 1,
+    Off,
+    On,
 }
 
 impl Not for FlipFlop {
@@ -40,276 +57,633 @@ impl Not for FlipFlop {
     }
 }
 
+impl From<FlipFlop> for Pulse {
+    fn from(value: FlipFlop) -> Self {
+        match value {
+            FlipFlop::Off => Pulse::Low,
+            FlipFlop::On => Pulse::High,
+        }
+    }
+}
+
+/// Index of a module in `Network::modules`.
+type ModuleId = usize;
+
 #[derive(Debug, Clone)]
-enum Module {
+enum ModuleKind {
     FlipFlop {
-        name: String,
         state: FlipFlop,
-        last_pulse: Option<Pulse>,
-        outputs: Box<[String]>,
     },
     Conjunction {
-        name: String,
-        previous_inputs: HashMap<String, Pulse>,
-        last_pulse: Option<Pulse>,
-        outputs: Box<[String]>,
-        /// On what button presses did this module last pulse high?
-        high_pulses: Vec<u64>,
-    },
-    Broadcaster {
-        outputs: Box<[String]>,
+        /// The last pulse received from each input, in the order of `Module::inputs`.
+        previous_inputs: Vec<Pulse>,
     },
+    Broadcaster,
+    /// A module that is only ever sent pulses, like `rx`.
+    Sink,
 }
 
-impl Module {
-    pub fn name(&self) -> String {
-        match self {
-            Module::FlipFlop {
-                name,
-                state: _,
-                outputs: _,
-                last_pulse: _,
-            } => name.to_owned(),
-            Module::Conjunction {
-                name,
-                previous_inputs: _,
-                outputs: _,
-                last_pulse: _,
-                high_pulses: _,
-            } => name.to_owned(),
-            Module::Broadcaster { outputs: _ } => "broadcaster".to_owned(),
-        }
-    }
-
-    pub fn outputs(&self) -> &[String] {
-        match self {
-            Module::FlipFlop {
-                name: _,
-                state: _,
-                outputs,
-                last_pulse: _,
-            } => outputs.as_ref(),
-            Module::Conjunction {
-                name: _,
-                previous_inputs: _,
-                outputs,
-                last_pulse: _,
-                high_pulses: _,
-            } => outputs.as_ref(),
-            Module::Broadcaster { outputs } => outputs.as_ref(),
-        }
-    }
+#[derive(Debug, Clone)]
+struct Module {
+    name: String,
+    kind: ModuleKind,
+    inputs: Vec<ModuleId>,
+    outputs: Box<[ModuleId]>,
+}
 
-    pub fn pulse(&mut self, name: String, pulse: Pulse) -> Option<Pulse> {
-        match self {
-            Module::FlipFlop {
-                name: _,
-                state,
-                outputs: _,
-                last_pulse,
-            } => {
-                if !matches!(pulse, Pulse::Low) {
-                    *last_pulse = None;
+impl Module {
+    /// Handles a pulse sent by the input at position `input`, returning the pulse it sends on.
+    fn receive(&mut self, input: usize, pulse: Pulse) -> Option<Pulse> {
+        match &mut self.kind {
+            ModuleKind::FlipFlop { state } => {
+                if pulse == Pulse::High {
                     return None;
                 }
-
                 *state = !*state;
-                let pulse = *state as u8;
-                // SAFETY: I'm ensuring that FlipFlop's On/Off variants are the same as Pulse's High/Low variants
-                let pulse = Some(unsafe { std::mem::transmute(pulse) });
-                *last_pulse = pulse;
-                return pulse;
+                Some((*state).into())
             }
-            Module::Conjunction {
-                name: _,
-                previous_inputs,
-                outputs: _,
-                last_pulse,
-                high_pulses,
-            } => {
-                previous_inputs.insert(name, pulse);
-
-                if previous_inputs.values().all(|v| matches!(v, Pulse::High)) {
-                    let pulse = Some(Pulse::Low);
-                    *last_pulse = pulse;
-                    return pulse;
+            ModuleKind::Conjunction { previous_inputs } => {
+                previous_inputs[input] = pulse;
+                if previous_inputs.iter().all(|p| *p == Pulse::High) {
+                    Some(Pulse::Low)
+                } else {
+                    Some(Pulse::High)
                 }
-                let pulse = Some(Pulse::High);
-                // SAFETY: This application is not multi-threaded, so multiple accesses to this static variable will not happen.
-                high_pulses.push(unsafe { PRESSES });
-                *last_pulse = pulse;
-                return pulse;
             }
-            Module::Broadcaster { outputs: _ } => Some(pulse),
+            ModuleKind::Broadcaster => Some(pulse),
+            ModuleKind::Sink => None,
         }
     }
 }
 
-impl FromStr for Module {
+/// A single pulse travelling from one module to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Event {
+    press: u64,
+    /// `None` for the pulse sent by the button.
+    sender: Option<ModuleId>,
+    pulse: Pulse,
+    receiver: ModuleId,
+}
+
+/// Which events a trace keeps.
+#[derive(Debug, Clone, Default)]
+struct TraceFilter {
+    /// Only keep events sent or received by this module.
+    module: Option<ModuleId>,
+    pulse: Option<Pulse>,
+}
+
+impl TraceFilter {
+    fn matches(&self, event: &Event) -> bool {
+        self.module
+            .is_none_or(|m| event.sender == Some(m) || event.receiver == m)
+            && self.pulse.is_none_or(|p| event.pulse == p)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Trace {
+    filter: TraceFilter,
+    events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PulseCounts {
+    low: u64,
+    high: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Network {
+    modules: Vec<Module>,
+    ids: HashMap<String, ModuleId>,
+    broadcaster: ModuleId,
+    /// How many times the button has been pressed.
+    presses: u64,
+    trace: Option<Trace>,
+}
+
+impl FromStr for Network {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (module, targets) = s.split(" -> ").collect_tuple().unwrap();
-        let outputs = targets.split(", ").map(|s| s.to_owned()).collect();
+        let mut modules: Vec<Module> = Vec::new();
+        let mut ids = HashMap::new();
+        let mut intern = |name: &str, modules: &mut Vec<Module>| {
+            *ids.entry(name.to_owned()).or_insert_with(|| {
+                modules.push(Module {
+                    name: name.to_owned(),
+                    kind: ModuleKind::Sink,
+                    inputs: Vec::new(),
+                    outputs: Box::new([]),
+                });
+                modules.len() - 1
+            })
+        };
+
+        for line in s.lines() {
+            let (module, targets) = line
+                .split_once(" -> ")
+                .ok_or_else(|| anyhow!("Module {:?} has no outputs", line))?;
+            let (kind, name) = match module.split_at(1) {
+                ("%", name) => (
+                    ModuleKind::FlipFlop {
+                        state: FlipFlop::Off,
+                    },
+                    name,
+                ),
+                ("&", name) => (
+                    ModuleKind::Conjunction {
+                        previous_inputs: Vec::new(),
+                    },
+                    name,
+                ),
+                _ if module == "broadcaster" => (ModuleKind::Broadcaster, module),
+                _ => return Err(anyhow!("Unknown module type in {:?}", module)),
+            };
 
-        if module == "broadcaster" {
-            return Ok(Module::Broadcaster { outputs });
+            let id = intern(name, &mut modules);
+            if !matches!(modules[id].kind, ModuleKind::Sink) {
+                return Err(anyhow!("Module {} is defined more than once", name));
+            }
+            let outputs = targets
+                .split(", ")
+                .map(|target| intern(target, &mut modules))
+                .collect();
+            modules[id].kind = kind;
+            modules[id].outputs = outputs;
         }
 
-        let (typ, name) = (module.chars().next().unwrap(), module[1..].to_owned());
-
-        match typ {
-            '%' => Ok(Module::FlipFlop {
-                name,
-                state: FlipFlop::Off,
-                outputs,
-                last_pulse: None,
-            }),
-            '&' => Ok(Module::Conjunction {
-                name,
-                previous_inputs: HashMap::new(),
-                outputs,
-                last_pulse: None,
-                high_pulses: Vec::new(),
-            }),
-            _ => unreachable!(),
+        for id in 0..modules.len() {
+            for output in modules[id].outputs.clone().iter() {
+                modules[*output].inputs.push(id);
+            }
+        }
+        for module in &mut modules {
+            if let ModuleKind::Conjunction { previous_inputs } = &mut module.kind {
+                *previous_inputs = vec![Pulse::Low; module.inputs.len()];
+            }
         }
+
+        let broadcaster = *ids
+            .get("broadcaster")
+            .ok_or_else(|| anyhow!("There is no broadcaster module"))?;
+        Ok(Self {
+            modules,
+            ids,
+            broadcaster,
+            presses: 0,
+            trace: None,
+        })
     }
 }
 
-fn input() -> HashMap<String, Rc<RefCell<Module>>> {
-    let modules = FILE_CONTENTS
-        .lines()
-        .map(|l| l.parse().unwrap())
-        .map(|m: Module| (m.name(), Rc::new(RefCell::new(m))))
-        .collect::<HashMap<_, _>>();
-
-    for (name, module) in &modules {
-        let module = module.borrow_mut();
-        for output in module.outputs() {
-            let output_module = modules.get(output);
-            if output_module.is_none() {
-                continue;
+impl Network {
+    pub fn id(&self, name: &str) -> anyhow::Result<ModuleId> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("There is no module named {}", name))
+    }
+
+    pub fn name(&self, id: ModuleId) -> &str {
+        &self.modules[id].name
+    }
+
+    /// Starts recording every event that passes `filter`.
+    pub fn record(&mut self, filter: TraceFilter) {
+        self.trace = Some(Trace {
+            filter,
+            events: Vec::new(),
+        });
+    }
+
+    /// Presses the button and delivers pulses until the network settles, calling `observe`
+    /// with every pulse sent, starting with the button's own.
+    pub fn press_button_with(&mut self, mut observe: impl FnMut(&Event)) -> PulseCounts {
+        self.presses += 1;
+        let mut counts = PulseCounts::default();
+        let mut queue = VecDeque::from([(None, self.broadcaster, Pulse::Low)]);
+
+        while let Some((sender, receiver, pulse)) = queue.pop_front() {
+            let event = Event {
+                press: self.presses,
+                sender,
+                pulse,
+                receiver,
+            };
+            match pulse {
+                Pulse::Low => counts.low += 1,
+                Pulse::High => counts.high += 1,
+            }
+            observe(&event);
+            if let Some(trace) = &mut self.trace {
+                if trace.filter.matches(&event) {
+                    trace.events.push(event);
+                }
+            }
+
+            let module = &mut self.modules[receiver];
+            let input = sender
+                .and_then(|sender| module.inputs.iter().position(|i| *i == sender))
+                .unwrap_or(0);
+            if let Some(next) = module.receive(input, pulse) {
+                queue.extend(
+                    module
+                        .outputs
+                        .iter()
+                        .map(|output| (Some(receiver), *output, next)),
+                );
+            }
+        }
+
+        counts
+    }
+
+    pub fn press_button(&mut self) -> PulseCounts {
+        self.press_button_with(|_| {})
+    }
+
+    pub fn format_event(&self, event: &Event) -> String {
+        let sender = event.sender.map_or("button", |id| self.name(id));
+        format!(
+            "press {}: {} -{}-> {}",
+            event.press,
+            sender,
+            event.pulse,
+            self.name(event.receiver)
+        )
+    }
+
+    /// Writes the recorded trace to `--trace-file`, or prints up to `--limit` events.
+    pub fn dump_trace(&self, args: &Args) -> anyhow::Result<()> {
+        let Some(trace) = &self.trace else {
+            return Ok(());
+        };
+        match &args.trace_file {
+            Some(path) => {
+                let mut file = BufWriter::new(File::create(path)?);
+                for event in &trace.events {
+                    writeln!(file, "{}", self.format_event(event))?;
+                }
+                println!("Wrote {} events to {}", trace.events.len(), path.display());
             }
-            let output_module = output_module.unwrap();
-            let output_module = &mut *output_module.borrow_mut();
-            match output_module {
-                Module::Conjunction {
-                    name: _,
-                    previous_inputs,
-                    outputs: _,
-                    last_pulse: _,
-                    high_pulses: _,
-                } => {
-                    previous_inputs.insert(name.to_owned(), Pulse::Low);
+            None => {
+                for event in trace.events.iter().take(args.limit) {
+                    println!("{}", self.format_event(event));
+                }
+                if trace.events.len() > args.limit {
+                    println!("... and {} more", trace.events.len() - args.limit);
                 }
-                _ => {}
             }
         }
+        Ok(())
+    }
+}
+
+/// The state of every flip-flop and every conjunction's memory, packed into bits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Snapshot {
+    bits: Vec<u64>,
+    len: usize,
+}
+
+impl Snapshot {
+    fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.bits.push(0);
+        }
+        if bit {
+            self.bits[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
     }
 
-    modules
+    fn get(&self, index: usize) -> bool {
+        self.bits[index / 64] >> (index % 64) & 1 == 1
+    }
 }
 
-fn press_button(input: &mut HashMap<String, Rc<RefCell<Module>>>) -> (usize, usize) {
-    let mut modules = VecDeque::new();
-    modules.push_front((Pulse::Low, input.get("broadcaster").unwrap().clone()));
-
-    let mut high_pulse_counter = 0;
-    // Low starts at one because the button push counts as a low
-    let mut low_pulse_counter = 1;
-
-    while let Some((output_pulse, module)) = modules.pop_front() {
-        let module = module.as_ref().borrow();
-        let name = module.name();
-        for output in module.outputs() {
-            match output_pulse {
-                Pulse::Low => low_pulse_counter += 1,
-                Pulse::High => high_pulse_counter += 1,
+impl Network {
+    /// Captures the module states, in module order. The press counter is not included, so
+    /// equal snapshots mean the network will behave identically from then on.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot {
+            bits: Vec::new(),
+            len: 0,
+        };
+        for module in &self.modules {
+            match &module.kind {
+                ModuleKind::FlipFlop { state } => snapshot.push(*state == FlipFlop::On),
+                ModuleKind::Conjunction { previous_inputs } => {
+                    for pulse in previous_inputs {
+                        snapshot.push(*pulse == Pulse::High);
+                    }
+                }
+                ModuleKind::Broadcaster | ModuleKind::Sink => {}
             }
-            let output_module_rc = input.get_mut(output);
-            if output_module_rc.is_none() {
-                continue;
+        }
+        snapshot
+    }
+
+    /// Puts every module back into the state captured by `snapshot`.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        if snapshot.len != self.snapshot().len {
+            return Err(anyhow!(
+                "Snapshot has {} bits but the network has {}",
+                snapshot.len,
+                self.snapshot().len
+            ));
+        }
+
+        let mut bits = (0..snapshot.len).map(|i| snapshot.get(i));
+        for module in &mut self.modules {
+            match &mut module.kind {
+                ModuleKind::FlipFlop { state } => {
+                    *state = if bits.next().unwrap() {
+                        FlipFlop::On
+                    } else {
+                        FlipFlop::Off
+                    };
+                }
+                ModuleKind::Conjunction { previous_inputs } => {
+                    for pulse in previous_inputs {
+                        *pulse = if bits.next().unwrap() {
+                            Pulse::High
+                        } else {
+                            Pulse::Low
+                        };
+                    }
+                }
+                ModuleKind::Broadcaster | ModuleKind::Sink => {}
             }
-            let output_module_rc = output_module_rc.unwrap().clone();
-            let mut output_module = output_module_rc.borrow_mut();
-            if let Some(new_pulse) = output_module.pulse(name.to_owned(), output_pulse) {
-                drop(output_module);
-                modules.push_back((new_pulse, output_module_rc));
+        }
+        Ok(())
+    }
+
+    /// A Graphviz diagram of the modules, shaped by type.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph network {\n");
+        for (id, module) in self.modules.iter().enumerate() {
+            let style = match module.kind {
+                ModuleKind::FlipFlop { .. } => "shape=box, style=filled, fillcolor=lightblue",
+                ModuleKind::Conjunction { .. } => {
+                    "shape=invhouse, style=filled, fillcolor=lightsalmon"
+                }
+                ModuleKind::Broadcaster => "shape=doublecircle, style=filled, fillcolor=palegreen",
+                ModuleKind::Sink => "shape=doubleoctagon, style=filled, fillcolor=lightgrey",
+            };
+            let prefix = match module.kind {
+                ModuleKind::FlipFlop { .. } => "%",
+                ModuleKind::Conjunction { .. } => "&",
+                ModuleKind::Broadcaster | ModuleKind::Sink => "",
+            };
+            dot += &format!(
+                "    m{} [label=\"{}{}\", {}];\n",
+                id, prefix, module.name, style
+            );
+        }
+        for (id, module) in self.modules.iter().enumerate() {
+            for output in module.outputs.iter() {
+                dot += &format!("    m{} -> m{};\n", id, output);
             }
         }
+        dot += "}\n";
+        dot
     }
+}
 
-    (high_pulse_counter, low_pulse_counter)
+fn input(args: &Args) -> anyhow::Result<Network> {
+    let mut network: Network = FILE_CONTENTS.parse()?;
+    if args.trace || args.trace_file.is_some() {
+        let filter = TraceFilter {
+            module: args
+                .trace_module
+                .as_deref()
+                .map(|name| network.id(name))
+                .transpose()?,
+            pulse: args.trace_pulse.as_deref().map(str::parse).transpose()?,
+        };
+        network.record(filter);
+    }
+    Ok(network)
 }
 
-pub fn part_one(_args: Args) {
-    let mut input = input();
-    let mut high_counter = 0;
-    let mut low_counter = 0;
-    for _ in 0..1000 {
-        let (high, low) = press_button(&mut input);
-        high_counter += high;
-        low_counter += low;
+pub fn part_one(args: Args) {
+    let mut network = input(&args).unwrap();
+    if let Some(path) = &args.render {
+        write_dot(path, &network.to_dot()).unwrap();
+    }
+
+    // Pulse counts after each press, so that a repeating state can skip the rest
+    let mut totals = vec![PulseCounts::default()];
+    let mut seen = HashMap::from([(network.snapshot(), 0)]);
+    while network.presses < 1000 {
+        let counts = network.press_button();
+        let last = totals[totals.len() - 1];
+        totals.push(PulseCounts {
+            low: last.low + counts.low,
+            high: last.high + counts.high,
+        });
+
+        if let Some(start) = seen.insert(network.snapshot(), network.presses) {
+            let period = network.presses - start;
+            let (cycles, rest) = (1000 - start).div_rem(&period);
+            let extrapolate = |f: fn(&PulseCounts) -> u64| {
+                f(&totals[start as usize])
+                    + cycles * (f(&totals[network.presses as usize]) - f(&totals[start as usize]))
+                    + f(&totals[(start + rest) as usize])
+                    - f(&totals[start as usize])
+            };
+            totals.push(PulseCounts {
+                low: extrapolate(|c| c.low),
+                high: extrapolate(|c| c.high),
+            });
+            println!("Network state repeats every {} presses", period);
+            break;
+        }
     }
+    network.dump_trace(&args).unwrap();
+
+    let PulseCounts {
+        low: low_counter,
+        high: high_counter,
+    } = totals[totals.len() - 1];
     println!("High: {}, Low: {}", high_counter, low_counter);
     println!("Answer: {}", high_counter * low_counter);
 }
 
-pub fn part_two(_args: Args) {
-    let mut input = input();
+/// Presses to wait for a sub-network to repeat before giving up on decomposing it.
+const MAX_PRESSES: u64 = 1_000_000;
 
-    let outputs_to_gh = input
-        .iter()
-        .filter(|(_name, m)| {
-            let m = m.as_ref().borrow();
-            m.outputs().contains(&"gh".to_owned())
-        })
-        .map(|(n, m)| (n.to_owned(), m.to_owned()))
-        .collect::<HashMap<_, _>>();
+/// A group of modules that only talks to the rest of the network through the broadcaster
+/// and the conjunction feeding the sink.
+#[derive(Debug, Clone)]
+struct SubNetwork {
+    /// The module in the sub-network that feeds the conjunction.
+    output: ModuleId,
+    modules: Vec<ModuleId>,
+}
+
+/// The presses on which a sub-network sends a high pulse, `offset` mod `period` starting
+/// from press `first`.
+#[derive(Debug, Clone, Copy)]
+struct Cycle {
+    first: u64,
+    period: u64,
+    offset: u64,
+}
+
+impl Network {
+    /// Finds the conjunction feeding `sink` and splits the modules feeding it into
+    /// independent sub-networks, one per conjunction input.
+    pub fn decompose(&self, sink: ModuleId) -> anyhow::Result<(ModuleId, Vec<SubNetwork>)> {
+        let sink_name = self.name(sink);
+        let &[feeder] = self.modules[sink].inputs.as_slice() else {
+            return Err(anyhow!("{} is not fed by exactly one module", sink_name));
+        };
+        if !matches!(self.modules[feeder].kind, ModuleKind::Conjunction { .. }) {
+            return Err(anyhow!("{} is not fed by a conjunction", sink_name));
+        }
 
-    for _ in 0..100_000 {
-        // SAFETY: This application is not multi-threaded, so multiple accesses to this static variable will not happen.
-        unsafe { PRESSES += 1 };
-        press_button(&mut input);
+        let mut owner = vec![None; self.modules.len()];
+        let mut sub_networks = Vec::new();
+        for (index, output) in self.modules[feeder].inputs.iter().enumerate() {
+            let mut modules = Vec::new();
+            let mut stack = vec![*output];
+            while let Some(id) = stack.pop() {
+                if id == self.broadcaster || owner[id] == Some(index) {
+                    continue;
+                }
+                if id == feeder || id == sink || owner[id].is_some() {
+                    return Err(anyhow!(
+                        "The modules feeding {} through {} are not independent",
+                        self.name(feeder),
+                        self.name(*output)
+                    ));
+                }
+                owner[id] = Some(index);
+                modules.push(id);
+                stack.extend(self.modules[id].inputs.iter().copied());
+            }
+            sub_networks.push(SubNetwork {
+                output: *output,
+                modules,
+            });
+        }
+
+        Ok((feeder, sub_networks))
     }
 
-    println!(
-        "{:#?}",
-        outputs_to_gh
+    /// Presses the button until every sub-network has sent `feeder` a high pulse three
+    /// times, checking that the gaps between them repeat. The network is put back the way it
+    /// was afterwards.
+    pub fn measure_cycles(
+        &mut self,
+        feeder: ModuleId,
+        sub_networks: &[SubNetwork],
+    ) -> anyhow::Result<Vec<Cycle>> {
+        let (initial, presses) = (self.snapshot(), self.presses);
+        let mut highs: HashMap<ModuleId, Vec<u64>> = HashMap::new();
+        let done = |highs: &HashMap<ModuleId, Vec<u64>>| {
+            sub_networks
+                .iter()
+                .all(|sub| highs.get(&sub.output).is_some_and(|h| h.len() >= 3))
+        };
+
+        while !done(&highs) {
+            if self.presses >= MAX_PRESSES {
+                return Err(anyhow!(
+                    "Not every sub-network repeated within {} presses",
+                    MAX_PRESSES
+                ));
+            }
+            self.press_button_with(|event| {
+                if let (Some(sender), Pulse::High) = (event.sender, event.pulse) {
+                    if event.receiver == feeder {
+                        let presses = highs.entry(sender).or_default();
+                        if presses.last() != Some(&event.press) {
+                            presses.push(event.press);
+                        }
+                    }
+                }
+            });
+        }
+        self.restore(&initial)?;
+        self.presses = presses;
+
+        sub_networks
             .iter()
-            .filter_map(|(k, m)| {
-                let m = m.as_ref().borrow();
-                return match &*m {
-                    Module::Conjunction {
-                        name: _,
-                        previous_inputs: _,
-                        last_pulse: _,
-                        outputs: _,
-                        high_pulses,
-                    } => Some((k, high_pulses.to_owned())),
-                    _ => None,
-                };
+            .map(|sub| {
+                let presses = &highs[&sub.output];
+                let (first, second, third) = (presses[0], presses[1], presses[2]);
+                let period = second - first;
+                if third - second != period {
+                    return Err(anyhow!(
+                        "{} pulses high on presses {}, {} and {}, which is not a cycle",
+                        self.name(sub.output),
+                        first,
+                        second,
+                        third
+                    ));
+                }
+                Ok(Cycle {
+                    first,
+                    period,
+                    offset: first % period,
+                })
             })
-            .collect::<HashMap<_, _>>()
-    );
-
-    let pulses_till_all_high = outputs_to_gh
-        .values()
-        .filter_map(|m| {
-            let m = m.as_ref().borrow();
-            return match &*m {
-                Module::Conjunction {
-                    name: _,
-                    previous_inputs: _,
-                    last_pulse: _,
-                    outputs: _,
-                    high_pulses,
-                } => Some(high_pulses.to_owned()),
-                _ => None,
-            };
-        })
-        .fold(1u64, |acc, v| acc.lcm(&v[0]));
+            .collect()
+    }
+}
+
+/// The first press on or after every cycle's first high pulse where they all pulse high.
+fn combine(cycles: &[Cycle]) -> anyhow::Result<u64> {
+    // Chinese remainder theorem, merging one congruence at a time. When every offset is zero
+    // this is just the LCM of the periods, which still has to be rounded up past `earliest`.
+    let (mut offset, mut period) = (0i128, 1i128);
+    for cycle in cycles {
+        let (b, m) = (cycle.offset as i128, cycle.period as i128);
+        let gcd = period.extended_gcd(&m);
+        if (b - offset) % gcd.gcd != 0 {
+            return Err(anyhow!("The sub-network cycles never line up"));
+        }
+        let lcm = period / gcd.gcd * m;
+        offset = (offset + (b - offset) / gcd.gcd * gcd.x % (m / gcd.gcd) * period).rem_euclid(lcm);
+        period = lcm;
+    }
+
+    let earliest = cycles.iter().map(|c| c.first).max().unwrap_or(0) as i128;
+    let mut press = offset;
+    if press < earliest {
+        press += (earliest - press + period - 1) / period * period;
+    }
+    u64::try_from(press).map_err(|_| anyhow!("The answer does not fit in a u64"))
+}
+
+pub fn part_two(args: Args) {
+    let mut network = input(&args).unwrap();
+    if let Some(path) = &args.render {
+        write_dot(path, &network.to_dot()).unwrap();
+    }
+
+    let sink = network.id(args.sink.as_deref().unwrap_or("rx")).unwrap();
+    let (feeder, sub_networks) = network.decompose(sink).unwrap();
+    let cycles = network.measure_cycles(feeder, &sub_networks).unwrap();
+    network.dump_trace(&args).unwrap();
+
+    for (sub, cycle) in sub_networks.iter().zip(&cycles) {
+        println!(
+            "{} ({} modules) pulses high every {} presses starting at press {}",
+            network.name(sub.output),
+            sub.modules.len(),
+            cycle.period,
+            cycle.first
+        );
+    }
+
+    let pulses_till_all_high = combine(&cycles).unwrap();
 
     println!("Pulses till all high: {}", pulses_till_all_high);
 }
@@ -1,9 +1,12 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 use macros::solutions;
 
 #[macro_use]
 extern crate anyhow;
 
+mod bit_grid;
 mod day_21;
 mod day_eight;
 mod day_eighteen;
@@ -25,6 +28,8 @@ mod day_three;
 mod day_twelve;
 mod day_twenty;
 mod day_two;
+mod render;
+mod series;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -33,6 +38,93 @@ pub struct Args {
     day: u8,
     #[arg(long, short, value_parser = clap::value_parser!(u8).range(1..=2))]
     part: u8,
+    /// Spelled out digits as comma separated `word=value` pairs, e.g. `zero=0,one=1` (day 1)
+    #[arg(long)]
+    words: Option<String>,
+    /// Number of copies each record is unfolded into (day 12)
+    #[arg(long)]
+    unfold: Option<usize>,
+    /// Print the valid arrangements of the record on this line (day 12)
+    #[arg(long)]
+    explore: Option<usize>,
+    /// Blocks the crucible must move in a straight line before it can turn (day 17)
+    #[arg(long)]
+    min_run: Option<u8>,
+    /// Blocks the crucible can move in a straight line before it must turn (day 17)
+    #[arg(long)]
+    max_run: Option<u8>,
+    /// Whether the crucible must have moved the minimum run before stopping at the end (day 17)
+    #[arg(long)]
+    stop_requires_min: Option<bool>,
+    /// Let the crucible reverse direction as if it were a turn (day 17)
+    #[arg(long)]
+    allow_reverse: bool,
+    /// Starting cell as `x,y` (day 17)
+    #[arg(long, value_parser = parse_point)]
+    start: Option<(usize, usize)>,
+    /// Ending cell as `x,y` (day 17)
+    #[arg(long, value_parser = parse_point)]
+    end: Option<(usize, usize)>,
+    /// Read the puzzle input from this file instead of the bundled one (days 15 and 18)
+    #[arg(long)]
+    input: Option<PathBuf>,
+    /// Write a visualization of the solution to this path
+    #[arg(long)]
+    render: Option<PathBuf>,
+    /// Number of spin cycles to record when rendering, by default until the loop has repeated
+    /// once (day 14)
+    #[arg(long)]
+    cycles: Option<usize>,
+    /// Lowest value any part rating can take (day 19)
+    #[arg(long, default_value_t = 1)]
+    min_rating: u32,
+    /// Highest value any part rating can take (day 19)
+    #[arg(long, default_value_t = 4000)]
+    max_rating: u32,
+    /// Part to follow through the workflows when rendering, as `{x=..,m=..,a=..,s=..}` (day 19)
+    #[arg(long)]
+    rating: Option<String>,
+    /// Print the normalized dig plan instead of solving it (day 18)
+    #[arg(long)]
+    convert: bool,
+    /// Number of steps the elf takes (day 21)
+    #[arg(long)]
+    steps: Option<u64>,
+    /// Check the garden solver against a brute force walk at small step counts (day 21)
+    #[arg(long)]
+    brute_force: bool,
+    /// Write the values recorded while simulating to this CSV file (day 21)
+    #[arg(long)]
+    csv: Option<PathBuf>,
+    /// Include first and second finite differences in recorded series (day 21)
+    #[arg(long)]
+    differences: bool,
+    /// Print the intermediate state after every step
+    #[arg(long)]
+    trace: bool,
+    /// Module that should receive a single low pulse, instead of `rx` (day 20)
+    #[arg(long)]
+    sink: Option<String>,
+    /// Write the traced events to this file instead of printing them (day 20)
+    #[arg(long)]
+    trace_file: Option<PathBuf>,
+    /// Only trace pulses sent or received by this module (day 20)
+    #[arg(long)]
+    trace_module: Option<String>,
+    /// Only trace `low` or `high` pulses (day 20)
+    #[arg(long)]
+    trace_pulse: Option<String>,
+    /// Maximum number of items printed by exploration modes
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+}
+
+fn parse_point(s: &str) -> Result<(usize, usize), String> {
+    let (x, y) = s
+        .split_once(',')
+        .ok_or_else(|| format!("Expected x,y but got {:?}", s))?;
+    let parse = |n: &str| n.trim().parse::<usize>().map_err(|e| e.to_string());
+    Ok((parse(x)?, parse(y)?))
 }
 
 pub type Solution = fn(Args) -> ();
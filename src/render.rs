@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use plotters::{coord::Shift, prelude::*};
+
+/// Something that can be drawn onto any plotters backend.
+pub trait Drawing {
+    /// The size of the image in pixels.
+    fn size(&self) -> (u32, u32);
+
+    fn draw<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>) -> anyhow::Result<()>
+    where
+        DB::ErrorType: 'static;
+}
+
+/// Writes `drawing` to `path`, picking SVG or PNG output from the file extension.
+pub fn render_to_file(path: &Path, drawing: &impl Drawing) -> anyhow::Result<()> {
+    let size = drawing.size();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("svg") => {
+            let area = SVGBackend::new(path, size).into_drawing_area();
+            drawing.draw(&area)?;
+            area.present()?;
+        }
+        Some("png") => {
+            let area = BitMapBackend::new(path, size).into_drawing_area();
+            drawing.draw(&area)?;
+            area.present()?;
+        }
+        _ => return Err(anyhow!("Expected a .svg or .png render path")),
+    }
+    println!("Rendered {}", path.display());
+    Ok(())
+}
+
+/// Writes a Graphviz diagram to `path`, which must have a `.dot` extension.
+pub fn write_dot(path: &Path, dot: &str) -> anyhow::Result<()> {
+    if path.extension().and_then(|e| e.to_str()) != Some("dot") {
+        return Err(anyhow!("Expected a .dot render path"));
+    }
+    std::fs::write(path, dot)?;
+    println!("Rendered {}", path.display());
+    Ok(())
+}
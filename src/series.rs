@@ -0,0 +1,143 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use plotters::{coord::Shift, prelude::*};
+
+use crate::render::Drawing;
+
+/// A value sampled at increasing steps of a simulation, e.g. plots reachable after each step.
+#[derive(Debug, Clone)]
+pub struct Series {
+    step_label: String,
+    value_label: String,
+    points: Vec<(i64, i64)>,
+    /// Also write and plot the first and second finite differences, which are constant for
+    /// linear and quadratic growth respectively.
+    differences: bool,
+}
+
+impl Series {
+    pub fn new(step_label: &str, value_label: &str, differences: bool) -> Self {
+        Self {
+            step_label: step_label.to_owned(),
+            value_label: value_label.to_owned(),
+            points: Vec::new(),
+            differences,
+        }
+    }
+
+    pub fn record(&mut self, step: i64, value: i64) {
+        self.points.push((step, value));
+    }
+
+    /// Each value minus the one before it, repeated `order` times. The first `order` steps
+    /// have no difference.
+    pub fn difference(&self, order: usize) -> Vec<(i64, i64)> {
+        let mut points = self.points.clone();
+        for _ in 0..order {
+            points = points
+                .windows(2)
+                .map(|pair| (pair[1].0, pair[1].1 - pair[0].1))
+                .collect();
+        }
+        points
+    }
+
+    pub fn write_csv(&self, path: &Path) -> anyhow::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let (first, second) = (self.difference(1), self.difference(2));
+
+        write!(file, "{},{}", self.step_label, self.value_label)?;
+        if self.differences {
+            write!(file, ",first difference,second difference")?;
+        }
+        writeln!(file)?;
+
+        for (i, (step, value)) in self.points.iter().enumerate() {
+            write!(file, "{},{}", step, value)?;
+            if self.differences {
+                let cell = |points: &[(i64, i64)], order: usize| {
+                    i.checked_sub(order)
+                        .and_then(|i| points.get(i))
+                        .map(|(_, d)| d.to_string())
+                        .unwrap_or_default()
+                };
+                write!(file, ",{},{}", cell(&first, 1), cell(&second, 2))?;
+            }
+            writeln!(file)?;
+        }
+
+        file.flush()?;
+        println!("Wrote {} rows to {}", self.points.len(), path.display());
+        Ok(())
+    }
+
+    fn draw_chart<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+        caption: &str,
+        points: &[(i64, i64)],
+        color: RGBColor,
+    ) -> anyhow::Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        let bounds = |values: &mut dyn Iterator<Item = i64>| {
+            let (min, max) = values.fold((i64::MAX, i64::MIN), |(min, max), v| {
+                (min.min(v), max.max(v))
+            });
+            if min > max {
+                0..1
+            } else {
+                min..max.max(min + 1)
+            }
+        };
+        let steps = bounds(&mut points.iter().map(|(s, _)| *s));
+        let values = bounds(&mut points.iter().map(|(_, v)| *v));
+
+        let mut chart = ChartBuilder::on(area)
+            .caption(caption, ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(80)
+            .build_cartesian_2d(steps, values)?;
+        chart
+            .configure_mesh()
+            .x_desc(self.step_label.as_str())
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            points.iter().copied(),
+            color.stroke_width(2),
+        ))?;
+        Ok(())
+    }
+}
+
+impl Drawing for Series {
+    fn size(&self) -> (u32, u32) {
+        if self.differences {
+            (1000, 1200)
+        } else {
+            (1000, 600)
+        }
+    }
+
+    fn draw<DB: DrawingBackend>(&self, area: &DrawingArea<DB, Shift>) -> anyhow::Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        area.fill(&WHITE)?;
+        if !self.differences {
+            return self.draw_chart(area, &self.value_label, &self.points, BLUE);
+        }
+
+        let panels = area.split_evenly((3, 1));
+        self.draw_chart(&panels[0], &self.value_label, &self.points, BLUE)?;
+        self.draw_chart(&panels[1], "First difference", &self.difference(1), RED)?;
+        self.draw_chart(&panels[2], "Second difference", &self.difference(2), GREEN)?;
+        Ok(())
+    }
+}